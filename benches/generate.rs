@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use axum::{Router, body::Body, routing::get};
+use criterion::{Criterion, criterion_group, criterion_main};
+use http::Request;
+use tower::ServiceExt;
+use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+
+fn router(config: TraceIdConfig) -> Router {
+    add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config)
+}
+
+fn generate(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let configs = [
+        ("system clock", TraceIdConfig::default()),
+        (
+            "coarse clock",
+            TraceIdConfig::builder()
+                .coarse_clock(Duration::from_millis(1))
+                .build()
+                .unwrap(),
+        ),
+    ];
+
+    for (name, config) in configs {
+        let router = router(config);
+        c.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| {
+                let router = router.clone();
+                async move {
+                    let request = Request::get("/").body(Body::empty()).unwrap();
+                    router.oneshot(request).await.unwrap()
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, generate);
+criterion_main!(benches);
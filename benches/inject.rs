@@ -0,0 +1,44 @@
+use axum::{Router, body::Body, routing::get};
+use criterion::{Criterion, criterion_group, criterion_main};
+use http::Request;
+use tower::ServiceExt;
+use trace_id_layer::{TraceId, add_trace_id_middleware};
+
+async fn handler(trace_id: TraceId) -> String {
+    trace_id.to_string()
+}
+
+fn router() -> Router {
+    add_trace_id_middleware(Router::new().route("/", get(handler)))
+}
+
+fn inject(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let router = router();
+
+    c.bench_function("without header", |b| {
+        b.to_async(&rt).iter(|| {
+            let router = router.clone();
+            async move {
+                let request = Request::get("/").body(Body::empty()).unwrap();
+                router.oneshot(request).await.unwrap()
+            }
+        })
+    });
+
+    c.bench_function("with header", |b| {
+        b.to_async(&rt).iter(|| {
+            let router = router.clone();
+            async move {
+                let request = Request::get("/")
+                    .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+                    .body(Body::empty())
+                    .unwrap();
+                router.oneshot(request).await.unwrap()
+            }
+        })
+    });
+}
+
+criterion_group!(benches, inject);
+criterion_main!(benches);
@@ -19,6 +19,9 @@ async fn main() -> anyhow::Result<()> {
         shutdown_signal().await;
     });
 
+    // Every request span is closed at this point. The `fmt` subscriber writes each line as it's
+    // logged, so there's nothing to flush: a buffered sink (e.g. an OTLP exporter) would be
+    // flushed here
     Ok(())
 }
 
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+
+use crate::TraceId;
+
+/// One record per request, passed to the [`on_complete`](crate::TraceIdConfigBuilder::on_complete)
+/// callback.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AuditRecord {
+    pub trace_id: TraceId,
+    pub method: Method,
+    /// The request path, without the query string.
+    pub path: String,
+    pub status: StatusCode,
+    /// Time from the request reaching the middleware to the response head being produced. Streamed
+    /// bodies may still be sending.
+    pub latency: Duration,
+}
@@ -0,0 +1,214 @@
+use axum::extract::FromRequestParts;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode, request::Parts};
+
+use crate::log::{error, warn};
+
+/// The W3C `baggage` header.
+pub const BAGGAGE_HEADER: HeaderName = HeaderName::from_static("baggage");
+
+/// The longest `baggage` header read, and written, in bytes, as in the W3C Baggage spec.
+pub const MAX_BAGGAGE_BYTES: usize = 8192;
+
+/// The most entries read from, and written to, a `baggage` header, as in the W3C Baggage spec.
+pub const MAX_BAGGAGE_ENTRIES: usize = 180;
+
+/// Key/value context propagated across services alongside the trace ID, such as a tenant or
+/// feature flags, read from the W3C [`baggage`](https://www.w3.org/TR/baggage/) header.
+///
+/// Enable it with [`baggage`](crate::TraceIdConfigBuilder::baggage), then use it as an extractor,
+/// and [`inject`](Self::inject) it into outbound requests. Values are percent-decoded (a `+` stays
+/// a `+`), and metadata properties (`;...` after a value) are dropped. Within the spec's limits, a
+/// header longer than [`MAX_BAGGAGE_BYTES`] is ignored altogether, and entries past the
+/// [`MAX_BAGGAGE_ENTRIES`]th are dropped, with a warning.
+///
+/// ```rust
+/// use http::HeaderMap;
+/// use trace_id_layer::Baggage;
+///
+/// async fn my_handler(baggage: Baggage) -> HeaderMap {
+///     let tenant = baggage.get("tenant").unwrap_or("<none>");
+///     tracing::info!("handling for tenant {tenant}");
+///
+///     // Forward it downstream
+///     let mut outbound = HeaderMap::new();
+///     baggage.inject(&mut outbound);
+///     outbound
+/// }
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert("baggage", "tenant=acme, flags=beta%2Cdark;ttl=60, query=a+b, bad=%+1".parse().unwrap());
+/// let baggage = Baggage::from_headers(&headers);
+/// assert_eq!(baggage.get("tenant"), Some("acme"));
+/// assert_eq!(baggage.get("flags"), Some("beta,dark"));
+/// assert_eq!(baggage.get("query"), Some("a+b"));
+/// assert_eq!(baggage.get("bad"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baggage(Vec<(String, String)>);
+
+impl Baggage {
+    /// Parses the `baggage` headers in `headers`, skipping malformed entries.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut entries = Vec::new();
+        let mut len = 0;
+        for value in headers.get_all(BAGGAGE_HEADER) {
+            len += value.len();
+            if len > MAX_BAGGAGE_BYTES {
+                warn!("Ignoring baggage longer than {MAX_BAGGAGE_BYTES} bytes");
+                return Self::default();
+            }
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            entries.extend(value.split(',').filter_map(parse_entry));
+        }
+        if entries.len() > MAX_BAGGAGE_ENTRIES {
+            warn!("Dropping baggage entries past the {MAX_BAGGAGE_ENTRIES}th");
+            entries.truncate(MAX_BAGGAGE_ENTRIES);
+        }
+        Self(entries)
+    }
+
+    /// The value of `key`, if present.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets `key` to `value`, replacing any previous value.
+    ///
+    /// Keys must be HTTP tokens, as in the spec: letters, digits and ``!#$%&'*+-.^_`|~``. Other
+    /// keys would corrupt the header, so they're skipped, with a warning. Values can be anything,
+    /// they're percent-encoded on [`inject`](Self::inject).
+    ///
+    /// ```rust
+    /// use trace_id_layer::Baggage;
+    ///
+    /// let mut baggage = Baggage::default();
+    /// baggage.insert("tenant", "acme corp");
+    /// baggage.insert("bad key", "ignored");
+    /// baggage.insert("a=b", "ignored");
+    /// assert_eq!(baggage.iter().collect::<Vec<_>>(), [("tenant", "acme corp")]);
+    /// ```
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let (key, value) = (key.into(), value.into());
+        if !is_token(&key) {
+            warn!(key = %key, "Skipping baggage entry with an invalid key");
+            return;
+        }
+        match self.0.iter_mut().find(|(entry_key, _)| *entry_key == key) {
+            Some((_, entry_value)) => *entry_value = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    /// The entries, in header order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Sets the `baggage` header in `headers` for an outbound request, unless there's no entry.
+    ///
+    /// Entries that would take the header past the spec's limits are left out.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        let mut header = String::new();
+        for (key, value) in self.0.iter().take(MAX_BAGGAGE_ENTRIES) {
+            let entry = format!("{key}={}", percent_encode(value));
+            if header.len() + entry.len() + 1 > MAX_BAGGAGE_BYTES {
+                break;
+            }
+            if !header.is_empty() {
+                header.push(',');
+            }
+            header.push_str(&entry);
+        }
+        if header.is_empty() {
+            return;
+        }
+        match HeaderValue::try_from(header) {
+            Ok(value) => {
+                headers.insert(BAGGAGE_HEADER, value);
+            }
+            Err(e) => error!(error = ?e, "Unable to convert baggage to a header value"),
+        }
+    }
+}
+
+/// Parses a `key=value;properties` list member.
+fn parse_entry(entry: &str) -> Option<(String, String)> {
+    let (key, value) = entry.split_once('=')?;
+    let value = value.split(';').next().unwrap_or_default();
+    let key = key.trim();
+    if !is_token(key) {
+        return None;
+    }
+    Some((key.to_owned(), percent_decode(value.trim())?))
+}
+
+/// Whether `key` is a valid baggage key, an HTTP token.
+fn is_token(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+/// Decodes `%XX` escapes, of exactly two hex digits. Unlike form encoding, `+` is a literal plus,
+/// not a space.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            // `from_str_radix` alone would take a sign, as in `%+1`
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Percent-encodes everything but the characters allowed unencoded in a baggage value.
+fn percent_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut encoded, byte| {
+        match byte {
+            b'!' | b'#'..=b'+' | b'-'..=b':' | b'<'..=b'[' | b']'..=b'~' if byte != b'%' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+        encoded
+    })
+}
+
+impl<S> FromRequestParts<S> for Baggage
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "Baggage extension missing. Did you enable TraceIdConfigBuilder::baggage?";
+        parts
+            .extensions
+            .get::<Baggage>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+use axum::body::{Body, Bytes, HttpBody};
+use http::HeaderName;
+use http_body::{Frame, SizeHint};
+
+use tracing::{Level, Span};
+
+use crate::{
+    TraceId,
+    log::{debug, error, event, info},
+};
+
+/// Request body wrapper that logs the trace ID found in the request trailers, if any.
+pub(crate) struct TrailerTraceId {
+    inner: Body,
+    trace_id: TraceId,
+    header_name: HeaderName,
+}
+
+impl TrailerTraceId {
+    pub(crate) fn new(inner: Body, trace_id: TraceId, header_name: HeaderName) -> Self {
+        Self {
+            inner,
+            trace_id,
+            header_name,
+        }
+    }
+}
+
+impl HttpBody for TrailerTraceId {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &frame
+            && let Some(value) = frame.trailers_ref().and_then(|t| t.get(&self.header_name))
+        {
+            match TraceId::try_from(value) {
+                Ok(trailer_id) if trailer_id == self.trace_id => {
+                    debug!(trace_id = %self.trace_id, "Request trailers carry the same trace_id")
+                }
+                Ok(trailer_id) => info!(
+                    trace_id = %self.trace_id,
+                    trailer_trace_id = %trailer_id,
+                    "Request trailers carry a different trace_id"
+                ),
+                Err(e) => error!(
+                    trace_id = %self.trace_id,
+                    error = %e,
+                    "Invalid trace_id in request trailers"
+                ),
+            }
+        }
+
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Response body wrapper that logs the trace ID once the body has been fully streamed.
+pub(crate) struct EosLog {
+    inner: Body,
+    trace_id: TraceId,
+    level: Level,
+    start: Instant,
+    done: bool,
+    span: Option<Span>,
+    bytes: u64,
+}
+
+impl EosLog {
+    pub(crate) fn new(inner: Body, trace_id: TraceId, level: Level) -> Self {
+        Self {
+            inner,
+            trace_id,
+            level,
+            start: Instant::now(),
+            done: false,
+            span: None,
+            bytes: 0,
+        }
+    }
+
+    /// Also counts the bytes streamed, and records them and the stream duration as the
+    /// `body.bytes` and `body.duration_ms` fields of `span` at the end of the stream.
+    pub(crate) fn in_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+}
+
+impl HttpBody for EosLog {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+
+        if self.span.is_some()
+            && let Some(Ok(frame)) = &frame
+            && let Some(data) = frame.data_ref()
+        {
+            self.bytes += data.len() as u64;
+        }
+        let eos = match &frame {
+            None => true,
+            Some(Ok(frame)) => frame.is_trailers() || self.inner.is_end_stream(),
+            Some(Err(_)) => false,
+        };
+        if eos && !self.done {
+            self.done = true;
+            let duration = self.start.elapsed();
+            let bytes = self.span.as_ref().map(|span| {
+                span.record("body.bytes", self.bytes);
+                span.record("body.duration_ms", duration.as_millis() as u64);
+                self.bytes
+            });
+            event!(
+                self.level,
+                trace_id = %self.trace_id,
+                duration = ?duration,
+                bytes,
+                "Stream closed"
+            );
+        }
+
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
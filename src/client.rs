@@ -0,0 +1,136 @@
+//! A client-side layer setting the current trace ID on outbound requests.
+
+use std::task::{Context, Poll};
+
+use http::{HeaderName, HeaderValue, Request};
+use tower::{Layer, Service};
+
+use crate::{IdFormat, TRACE_ID_HEADER, TraceId, TraceIdConfig, current_trace_id};
+
+/// Sets the trace ID on outbound requests sent through a tower client stack, as both the trace ID
+/// header and a [`TraceId`] extension, so client-side layers further down read it uniformly.
+///
+/// The trace ID comes from the request's own `TraceId` extension if set, and otherwise from
+/// [`current_trace_id`], i.e. the request this service is handling in the current task. Requests
+/// sent from tasks started with a plain `tokio::spawn`, or outside of any request, are passed
+/// through untouched. A trace ID header already set on the request is kept.
+///
+/// It's the client-side counterpart of the middleware: use [`from_config`](Self::from_config) so
+/// the header name and [`IdFormat`] match what it reads.
+///
+/// ```rust
+/// use std::convert::Infallible;
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::{ServiceBuilder, ServiceExt};
+/// use trace_id_layer::{SetTraceIdLayer, TraceId, add_trace_id_middleware};
+///
+/// async fn handler() -> String {
+///     // Stands in for an HTTP client
+///     let client = ServiceBuilder::new()
+///         .layer(SetTraceIdLayer::new())
+///         .service_fn(|request: Request<()>| async move {
+///             assert!(request.extensions().get::<TraceId>().is_some());
+///             Ok::<_, Infallible>(request.headers()["x-trace-id"].to_str().unwrap().to_owned())
+///         });
+///     client.oneshot(Request::new(())).await.unwrap()
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SetTraceIdLayer {
+    header_name: HeaderName,
+    id_format: IdFormat,
+}
+
+impl SetTraceIdLayer {
+    pub fn new() -> Self {
+        Self {
+            header_name: TRACE_ID_HEADER,
+            id_format: IdFormat::default(),
+        }
+    }
+
+    /// Writes the trace ID the way `config` does: to its header, in its [`IdFormat`].
+    pub fn from_config(config: &TraceIdConfig) -> Self {
+        Self {
+            header_name: config.header_name().clone(),
+            id_format: config.id_format(),
+        }
+    }
+
+    /// Sets the header the trace ID is written to. Defaults to `x-trace-id`.
+    pub fn header_name(mut self, header_name: HeaderName) -> Self {
+        self.header_name = header_name;
+        self
+    }
+}
+
+impl Default for SetTraceIdLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for SetTraceIdLayer {
+    type Service = SetTraceId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetTraceId {
+            inner,
+            header_name: self.header_name.clone(),
+            id_format: self.id_format,
+        }
+    }
+}
+
+/// The service added by [`SetTraceIdLayer`].
+#[derive(Debug, Clone)]
+pub struct SetTraceId<S> {
+    inner: S,
+    header_name: HeaderName,
+    id_format: IdFormat,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SetTraceId<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let trace_id = request
+            .extensions()
+            .get::<TraceId>()
+            .cloned()
+            .or_else(current_trace_id);
+        if let Some(trace_id) = trace_id {
+            if !request.headers().contains_key(&self.header_name) {
+                let value = HeaderValue::try_from(trace_id.display(self.id_format).to_string())
+                    .unwrap_or_else(|_| trace_id.to_header_value());
+                request
+                    .headers_mut()
+                    .insert(self.header_name.clone(), value);
+            }
+            request.extensions_mut().insert(trace_id);
+        }
+        self.inner.call(request)
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::log::error;
+
+/// A clock read from memory, refreshed from the system clock by a background thread every
+/// `interval`, for generating UUIDv7 trace IDs under very high load.
+///
+/// Set it with [`coarse_clock`](crate::TraceIdConfigBuilder::coarse_clock). Reading it costs an
+/// atomic load instead of a clock call, but it lags the system clock by up to `interval`: trace
+/// IDs generated within the same interval share a timestamp, so their order is random, and their
+/// timestamp is only accurate to `interval`. UUIDv7 timestamps have millisecond precision anyway,
+/// so intervals of a few milliseconds lose little. The thread stops once every clone of the clock
+/// is dropped.
+///
+/// Where reading the clock is cheap (e.g. Linux with vDSO), drawing the random bits dominates
+/// generation and the gain is small: compare with `cargo bench --bench generate` first.
+#[derive(Debug, Clone)]
+pub struct CoarseClock(Arc<AtomicU64>);
+
+/// Stored instead of a timestamp when the refresh thread couldn't be started.
+const SYSTEM: u64 = 0;
+
+/// The shortest refresh interval, below which the thread would busy-loop.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+impl CoarseClock {
+    /// Starts a clock refreshed every `interval`, clamped to at least 1ms: UUIDv7 timestamps
+    /// can't be more precise, and a zero interval would keep a core busy.
+    pub fn new(interval: Duration) -> Self {
+        let interval = interval.max(MIN_INTERVAL);
+        let millis = Arc::new(AtomicU64::new(unix_millis()));
+        let weak = Arc::downgrade(&millis);
+        let refresh = thread::Builder::new()
+            .name("trace-id-clock".to_owned())
+            .spawn(move || {
+                while let Some(millis) = weak.upgrade() {
+                    millis.store(unix_millis(), Ordering::Relaxed);
+                    drop(millis);
+                    thread::sleep(interval);
+                }
+            });
+        if let Err(e) = refresh {
+            error!(error = %e, "Unable to start the coarse clock thread, using the system clock");
+            millis.store(SYSTEM, Ordering::Relaxed);
+        }
+        Self(millis)
+    }
+
+    /// The time of the last refresh.
+    pub fn now(&self) -> SystemTime {
+        match self.0.load(Ordering::Relaxed) {
+            SYSTEM => SystemTime::now(),
+            millis => UNIX_EPOCH + Duration::from_millis(millis),
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH);
+    now.map_or(1, |now| now.as_millis() as u64)
+}
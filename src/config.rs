@@ -0,0 +1,2569 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Display},
+    io,
+    ops::Deref,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::extract::FromRequestParts;
+#[cfg(feature = "tracing")]
+use axum::{body::Body, response::Response};
+#[cfg(feature = "tracing")]
+use http::Request;
+use http::{
+    Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    header::{InvalidHeaderName, InvalidHeaderValue},
+    request::Parts,
+};
+use uuid::{NoContext, Timestamp, Uuid};
+
+use crate::{
+    DEFAULT_MAX_HOPS, ParseTraceIdError, TRACE_ID_HEADER, TraceId, core, log::error,
+    marker::LastRequestFile, recent::RecentRequests, short_id, throttle::LogThrottle,
+};
+
+/// The default of [`TraceIdConfigBuilder::invalid_header_log_limit`].
+const DEFAULT_INVALID_HEADER_LOG_LIMIT: u32 = 100;
+
+/// Configuration for the trace ID middleware.
+///
+/// Build one with [`TraceIdConfig::builder`] and apply it with
+/// [`add_trace_id_middleware_with_config`](crate::add_trace_id_middleware_with_config).
+/// [`TraceIdConfig::default`] matches the behavior of
+/// [`add_trace_id_middleware`](crate::add_trace_id_middleware).
+///
+/// ```rust
+/// use trace_id_layer::{SpanField, TraceIdConfig};
+///
+/// let config = TraceIdConfig::builder()
+///     .span_field(SpanField::Datadog)
+///     .build().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceIdConfig {
+    pub(crate) header_name: HeaderName,
+    pub(crate) header_name_resolver: Option<HeaderNameFn>,
+    pub(crate) fallback_headers: Vec<HeaderName>,
+    pub(crate) span_field: SpanField,
+    pub(crate) id_format: IdFormat,
+    pub(crate) echo_header: bool,
+    pub(crate) echo_verbatim: bool,
+    pub(crate) echo_if: Option<EchoIfFn>,
+    pub(crate) echo_origins: Option<Vec<HeaderValue>>,
+    pub(crate) server_timing: bool,
+    pub(crate) require_inbound: bool,
+    pub(crate) require_inbound_methods: Vec<Method>,
+    pub(crate) skip_methods: Vec<Method>,
+    pub(crate) chain: bool,
+    pub(crate) request_seq: bool,
+    pub(crate) idempotency_header: Option<HeaderName>,
+    pub(crate) max_hops: Option<u32>,
+    pub(crate) dry_run: bool,
+    #[cfg(feature = "baggage")]
+    pub(crate) baggage: bool,
+    #[cfg(feature = "traceparent")]
+    pub(crate) trace_context: bool,
+    pub(crate) source: Option<SourceFn>,
+    pub(crate) async_source: Option<AsyncSourceFn>,
+    pub(crate) deterministic_v5: Option<(Uuid, V5NameFn)>,
+    pub(crate) short_ids: Option<usize>,
+    pub(crate) node_id: Option<u16>,
+    pub(crate) clock: Option<ClockFn>,
+    pub(crate) span_attributes: String,
+    pub(crate) correlation_headers: Vec<(Cow<'static, str>, HeaderName)>,
+    #[cfg(feature = "tracing")]
+    pub(crate) response_fields: Vec<(Cow<'static, str>, ResponseFieldFn)>,
+    #[cfg(feature = "tracing")]
+    pub(crate) span_fields: Option<MakeSpanFn>,
+    pub(crate) trailer_trace_id: bool,
+    #[cfg(feature = "log-mdc")]
+    pub(crate) log_mdc: bool,
+    #[cfg(feature = "slog")]
+    pub(crate) slog_logger: Option<slog::Logger>,
+    #[cfg(feature = "tracing")]
+    pub(crate) eos_level: tracing::Level,
+    #[cfg(feature = "tracing")]
+    pub(crate) span_covers_body: bool,
+    #[cfg(feature = "tracing")]
+    pub(crate) log_url: Option<Cow<'static, str>>,
+    #[cfg(feature = "tracing")]
+    pub(crate) record_http_version: bool,
+    #[cfg(feature = "tracing")]
+    pub(crate) record_inbound_age: bool,
+    #[cfg(feature = "tracing")]
+    pub(crate) access_log: Option<tracing::Level>,
+    #[cfg(feature = "tracing")]
+    pub(crate) access_log_fields: Vec<AccessLogField>,
+    #[cfg(feature = "tracing")]
+    pub(crate) quiet_methods: Vec<Method>,
+    #[cfg(feature = "signing")]
+    pub(crate) signing_key: Option<crate::signing::SigningKey>,
+    pub(crate) missing_response: Option<(StatusCode, Cow<'static, str>)>,
+    pub(crate) invalid_header: InvalidHeader,
+    pub(crate) inbound_trust: InboundTrust,
+    pub(crate) log_invalid_bytes: bool,
+    pub(crate) invalid_header_log: LogThrottle,
+    #[cfg(feature = "prometheus-exemplars")]
+    pub(crate) metrics: Option<crate::metrics::RequestMetrics>,
+    pub(crate) on_complete: Option<OnCompleteFn>,
+    pub(crate) last_request_file: Option<LastRequestFile>,
+    pub(crate) recent_requests: Option<RecentRequests>,
+}
+
+impl Default for TraceIdConfig {
+    fn default() -> Self {
+        Self {
+            header_name: TRACE_ID_HEADER,
+            header_name_resolver: None,
+            fallback_headers: Vec::new(),
+            span_field: SpanField::default(),
+            id_format: IdFormat::default(),
+            echo_header: false,
+            echo_verbatim: false,
+            echo_if: None,
+            echo_origins: None,
+            server_timing: false,
+            require_inbound: false,
+            require_inbound_methods: Vec::new(),
+            skip_methods: Vec::new(),
+            chain: false,
+            request_seq: false,
+            idempotency_header: None,
+            max_hops: None,
+            dry_run: false,
+            #[cfg(feature = "baggage")]
+            baggage: false,
+            #[cfg(feature = "traceparent")]
+            trace_context: false,
+            source: None,
+            async_source: None,
+            deterministic_v5: None,
+            short_ids: None,
+            node_id: None,
+            clock: None,
+            span_attributes: String::new(),
+            correlation_headers: Vec::new(),
+            #[cfg(feature = "tracing")]
+            response_fields: Vec::new(),
+            #[cfg(feature = "tracing")]
+            span_fields: None,
+            trailer_trace_id: false,
+            #[cfg(feature = "log-mdc")]
+            log_mdc: false,
+            #[cfg(feature = "slog")]
+            slog_logger: None,
+            #[cfg(feature = "tracing")]
+            eos_level: tracing::Level::DEBUG,
+            #[cfg(feature = "tracing")]
+            span_covers_body: false,
+            #[cfg(feature = "tracing")]
+            log_url: None,
+            #[cfg(feature = "tracing")]
+            record_http_version: false,
+            #[cfg(feature = "tracing")]
+            record_inbound_age: false,
+            #[cfg(feature = "tracing")]
+            access_log: None,
+            #[cfg(feature = "tracing")]
+            access_log_fields: AccessLogField::DEFAULT.to_vec(),
+            #[cfg(feature = "tracing")]
+            quiet_methods: Vec::new(),
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            missing_response: None,
+            invalid_header: InvalidHeader::default(),
+            inbound_trust: InboundTrust::default(),
+            log_invalid_bytes: false,
+            invalid_header_log: LogThrottle::new(DEFAULT_INVALID_HEADER_LOG_LIMIT),
+            #[cfg(feature = "prometheus-exemplars")]
+            metrics: None,
+            on_complete: None,
+            last_request_file: None,
+            recent_requests: None,
+        }
+    }
+}
+
+impl TraceIdConfig {
+    pub fn builder() -> TraceIdConfigBuilder {
+        TraceIdConfigBuilder::default()
+    }
+
+    /// Generates a trace ID for a request without a usable one.
+    pub(crate) fn generate(&self, parts: &Parts) -> TraceId {
+        let v5 = self
+            .deterministic_v5
+            .as_ref()
+            .and_then(|(namespace, name)| {
+                let name = (name.0)(parts)?;
+                Some(Uuid::new_v5(namespace, name.as_bytes()))
+            });
+        match (v5, self.short_ids) {
+            (Some(uuid), _) => TraceId::from(uuid),
+            (None, Some(len)) => short_id::generate(len),
+            (None, None) => TraceId::from(self.generate_v7()),
+        }
+    }
+
+    /// Generates a UUIDv7 from the configured [clock](TraceIdConfigBuilder::clock), with the
+    /// [node ID](TraceIdConfigBuilder::node_id) embedded.
+    pub(crate) fn generate_v7(&self) -> Uuid {
+        let uuid = match &self.clock {
+            Some(clock) => {
+                let now = clock.now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                let timestamp = Timestamp::from_unix(NoContext, now.as_secs(), now.subsec_nanos());
+                Uuid::new_v7(timestamp)
+            }
+            None => Uuid::now_v7(),
+        };
+        match self.node_id {
+            Some(node_id) => Uuid::from_u128(uuid.as_u128() & !0xffff | u128::from(node_id)),
+            None => uuid,
+        }
+    }
+
+    /// The current time from the configured [clock](TraceIdConfigBuilder::clock).
+    #[cfg(feature = "tracing")]
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock
+            .as_ref()
+            .map_or_else(SystemTime::now, ClockFn::now)
+    }
+
+    /// The header the trace ID of the request with `parts` is read from and echoed to, per the
+    /// [resolver](TraceIdConfigBuilder::header_name_resolver) if set.
+    pub(crate) fn header_name_for(&self, parts: &Parts) -> Cow<'_, HeaderName> {
+        match &self.header_name_resolver {
+            Some(resolver) => Cow::Owned(resolver.call(parts)),
+            None => Cow::Borrowed(&self.header_name),
+        }
+    }
+
+    /// Reads the trace ID from the configured header, also accepting
+    /// [short IDs](TraceIdConfigBuilder::short_ids) when enabled.
+    pub(crate) fn extract(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<Option<TraceId>, ParseTraceIdError> {
+        self.extract_from(headers, &self.header_name)
+    }
+
+    /// Reads the trace ID like [`extract`](Self::extract), from `header_name` instead.
+    pub(crate) fn extract_from(
+        &self,
+        headers: &HeaderMap,
+        header_name: &HeaderName,
+    ) -> Result<Option<TraceId>, ParseTraceIdError> {
+        match (core::extract_header(headers, header_name), self.short_ids) {
+            (Err(ParseTraceIdError::InvalidUuid { value, error }), Some(len)) => {
+                short_id::parse(&value, len)
+                    .map(Some)
+                    .ok_or(ParseTraceIdError::InvalidUuid { value, error })
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Reads the trace ID like [`extract_from`](Self::extract_from) or, if the header is missing,
+    /// from the first [fallback header](TraceIdConfigBuilder::fallback_headers) holding a valid
+    /// UUID, along with that header's name.
+    pub(crate) fn extract_with_fallback(
+        &self,
+        headers: &HeaderMap,
+        header_name: &HeaderName,
+    ) -> Result<Option<(TraceId, Option<&HeaderName>)>, ParseTraceIdError> {
+        if let Some(trace_id) = self.extract_from(headers, header_name)? {
+            return Ok(Some((trace_id, None)));
+        }
+        Ok(self
+            .extract_fallback(headers)
+            .map(|(trace_id, header_name)| (trace_id, Some(header_name))))
+    }
+
+    /// Reads the trace ID from the first [fallback header](TraceIdConfigBuilder::fallback_headers)
+    /// holding a valid UUID, along with that header's name.
+    pub(crate) fn extract_fallback(&self, headers: &HeaderMap) -> Option<(TraceId, &HeaderName)> {
+        if self.inbound_trust == InboundTrust::Untrusted {
+            return None;
+        }
+        self.fallback_headers.iter().find_map(|header_name| {
+            let trace_id = TraceId::try_from(headers.get(header_name)?).ok()?;
+            Some((trace_id, header_name))
+        })
+    }
+
+    /// The `response_attributes` span field for `response`, if any attribute is set.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn response_attributes(&self, response: &Response) -> Option<String> {
+        let attributes = self
+            .response_fields
+            .iter()
+            .filter_map(|(key, field)| Some(format!("{key}={}", (field.0)(response)?)))
+            .collect::<Vec<_>>();
+        (!attributes.is_empty()).then(|| attributes.join(" "))
+    }
+
+    /// Whether the trace ID is echoed on a response with `status`.
+    pub(crate) fn echoes(&self, status: StatusCode) -> bool {
+        self.echo_header
+            && self
+                .echo_if
+                .as_ref()
+                .is_none_or(|echo_if| (echo_if.0)(status))
+    }
+
+    /// Whether the trace ID is echoed to a request from `origin`, per
+    /// [`echo_origins`](TraceIdConfigBuilder::echo_origins). Requests without an `Origin` aren't
+    /// cross-origin.
+    pub(crate) fn echoes_to(&self, origin: Option<&HeaderValue>) -> bool {
+        match (&self.echo_origins, origin) {
+            (Some(echo_origins), Some(origin)) => echo_origins.contains(origin),
+            _ => true,
+        }
+    }
+
+    /// Whether any response header is added by the middleware.
+    pub(crate) fn sets_response_headers(&self) -> bool {
+        #[cfg(feature = "signing")]
+        if self.signing_key.is_some() {
+            return true;
+        }
+        self.echo_header || self.server_timing
+    }
+
+    /// Formats `uuid` the way this config writes trace IDs to headers.
+    ///
+    /// Use it for outbound requests, so they match what the middleware sends and accepts.
+    ///
+    /// ```rust
+    /// use trace_id_layer::{IdFormat, TraceIdConfig};
+    /// use uuid::Uuid;
+    ///
+    /// let uuid = Uuid::parse_str("0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e").unwrap();
+    /// let config = TraceIdConfig::builder().id_format(IdFormat::Simple).build().unwrap();
+    /// assert_eq!(config.format_trace_id(uuid), "0192f0c45b8e7c3a9d2e6f1a2b3c4d5e");
+    /// ```
+    pub fn format_trace_id(&self, uuid: Uuid) -> String {
+        self.id_format.format(uuid)
+    }
+
+    /// The header trace IDs are read from and written to.
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header_name
+    }
+
+    /// The key the trace ID is recorded under on the `http-request` span.
+    pub fn span_field(&self) -> SpanField {
+        self.span_field
+    }
+
+    /// How UUID trace IDs are written to headers.
+    pub fn id_format(&self) -> IdFormat {
+        self.id_format
+    }
+
+    /// Whether a trace ID handled by `self` would be read, written or recorded differently by
+    /// `other`.
+    pub(crate) fn conflicts_with(&self, other: &TraceIdConfig) -> bool {
+        self.header_name != other.header_name
+            || self.id_format != other.id_format
+            || self.span_field != other.span_field
+    }
+
+    /// Whether the end of the response stream is handled within the span, per
+    /// [`span_covers_body`](TraceIdConfigBuilder::span_covers_body), which an access log replaces.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn covers_body(&self) -> bool {
+        self.span_covers_body && !self.logs_access()
+    }
+
+    /// Whether the single [access log](TraceIdConfigBuilder::access_log) line replaces the
+    /// built-in request lifecycle logs.
+    pub(crate) fn logs_access(&self) -> bool {
+        #[cfg(feature = "tracing")]
+        return self.access_log.is_some();
+        #[cfg(not(feature = "tracing"))]
+        false
+    }
+
+    /// The level of the per-request info lines for requests with `method`, lowered by
+    /// [`quiet_methods`](TraceIdConfigBuilder::quiet_methods).
+    #[cfg(feature = "tracing")]
+    pub(crate) fn request_log_level(
+        &self,
+        method: &Method,
+        level: tracing::Level,
+    ) -> tracing::Level {
+        // More verbose levels compare greater
+        if self.quiet_methods.contains(method) {
+            level.max(tracing::Level::DEBUG)
+        } else {
+            level
+        }
+    }
+
+    /// Whether requests with `method` must carry a trace ID header.
+    pub(crate) fn requires_inbound(&self, method: &Method) -> bool {
+        self.require_inbound || self.require_inbound_methods.contains(method)
+    }
+
+    /// The response for requests rejected by
+    /// [`require_inbound`](TraceIdConfigBuilder::require_inbound).
+    pub(crate) fn missing_response(
+        &self,
+        header_name: &HeaderName,
+    ) -> (StatusCode, Cow<'static, str>) {
+        self.missing_response.clone().unwrap_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Cow::Owned(format!("Missing required {header_name} header")),
+            )
+        })
+    }
+
+    /// The response for requests rejected by [`InvalidHeader::Reject`].
+    pub(crate) fn invalid_response(&self, header_name: &HeaderName) -> (StatusCode, String) {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid {header_name} header"),
+        )
+    }
+
+    /// Renders `trace_id` the way the middleware writes it to headers, applying the [`IdFormat`]
+    /// to UUIDs.
+    ///
+    /// Use it to set trace IDs on outbound requests.
+    pub fn render(&self, trace_id: &TraceId) -> String {
+        trace_id.display(self.id_format).to_string()
+    }
+}
+
+/// The [`TraceIdConfig`] the middleware handling this request was configured with.
+///
+/// Use this as an extractor to read the active configuration, e.g. to format outbound trace
+/// headers consistently:
+///
+/// ```rust
+/// use trace_id_layer::{ActiveConfig, TraceId};
+///
+/// async fn my_handler(trace_id: TraceId, config: ActiveConfig) -> String {
+///     match trace_id.uuid() {
+///         Some(uuid) => config.format_trace_id(uuid),
+///         None => trace_id.to_string(),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ActiveConfig(pub(crate) Arc<TraceIdConfig>);
+
+impl Deref for ActiveConfig {
+    type Target = TraceIdConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for ActiveConfig
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "ActiveConfig extension missing. Did you apply add_trace_id_middleware?";
+        parts
+            .extensions
+            .get::<ActiveConfig>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
+
+/// The trace ID rendered the way the middleware handling this request writes it to headers, i.e.
+/// in its configured [`IdFormat`].
+///
+/// Use this as an extractor instead of `trace_id.to_string()` when the string should match the
+/// header, without the handler knowing the config:
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{FormattedTraceId, IdFormat, TraceIdConfig, add_trace_id_middleware_with_config};
+///
+/// async fn my_handler(trace_id: FormattedTraceId) -> String {
+///     format!("Quote {trace_id} when contacting support")
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder().id_format(IdFormat::Simple).build().unwrap();
+/// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(my_handler)), config);
+///
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, "Quote 0192f0c45b8e7c3a9d2e6f1a2b3c4d5e when contacting support");
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FormattedTraceId(String);
+
+impl FormattedTraceId {
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl Deref for FormattedTraceId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for FormattedTraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S> FromRequestParts<S> for FormattedTraceId
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "TraceId or ActiveConfig extension missing. Did you apply add_trace_id_middleware?";
+        let extensions = &parts.extensions;
+        extensions
+            .get::<TraceId>()
+            .zip(extensions.get::<ActiveConfig>())
+            .map(|(trace_id, config)| Self(config.render(trace_id)))
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
+
+/// Builder for [`TraceIdConfig`].
+#[derive(Debug, Default)]
+pub struct TraceIdConfigBuilder {
+    config: TraceIdConfig,
+    error: Option<ConfigError>,
+    /// The path and slot count of the [last request file](Self::last_request_file), created on
+    /// [build](Self::build).
+    last_request_file: Option<(PathBuf, usize)>,
+    /// The refresh interval of the [coarse clock](Self::coarse_clock), started on
+    /// [build](Self::build).
+    coarse_clock: Option<Duration>,
+}
+
+impl TraceIdConfigBuilder {
+    /// Sets the header trace IDs are read from and written to.
+    ///
+    /// Accepts a [`HeaderName`] or any string, which is validated and lowercased (as required on
+    /// the wire by HTTP/2) when the config is [built](Self::build). Defaults to `x-trace-id`.
+    ///
+    /// ```rust
+    /// use trace_id_layer::{ConfigError, TraceIdConfig};
+    ///
+    /// let config = TraceIdConfig::builder().header_name("X-Correlation-Id").build().unwrap();
+    /// assert_eq!(config.header_name(), "x-correlation-id");
+    ///
+    /// let error = TraceIdConfig::builder().header_name("x trace id").build().unwrap_err();
+    /// assert!(matches!(error, ConfigError::InvalidHeaderName { .. }));
+    /// ```
+    pub fn header_name(mut self, header_name: impl AsRef<str>) -> Self {
+        if let Some(header_name) = self.parse_header_name(header_name.as_ref()) {
+            self.config.header_name = header_name;
+        }
+        self
+    }
+
+    /// Sets the header trace IDs are read from and written to from the environment variable `var`,
+    /// so it can differ per deployment without recompiling.
+    ///
+    /// If `var` is unset, the header name is left as is (`x-trace-id` by default). If it's set but
+    /// isn't a valid header name, that's logged and the header name is left as is as well, rather
+    /// than failing the [build](Self::build). Like any other option, a later
+    /// [`header_name`](Self::header_name) call overrides it.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .header_name_from_env("TRACE_ID_HEADER_NAME")
+    ///     .build().unwrap();
+    /// # assert_eq!(config.header_name(), "x-trace-id");
+    /// ```
+    pub fn header_name_from_env(mut self, var: &str) -> Self {
+        let Ok(value) = std::env::var(var) else {
+            return self;
+        };
+        match HeaderName::from_bytes(value.as_bytes()) {
+            Ok(header_name) => self.config.header_name = header_name,
+            Err(e) => error!(
+                var,
+                value,
+                error = %e,
+                "Invalid trace ID header name in the environment, keeping '{}'",
+                self.config.header_name
+            ),
+        }
+        self
+    }
+
+    /// Picks the trace ID header per request, e.g. per tenant in a gateway whose tenants send trace
+    /// IDs under header names of their own.
+    ///
+    /// `resolver` returns the header to read the trace ID from, and to echo it to, for each
+    /// request. Return the [`header_name`](Self::header_name) for requests it doesn't know, as the
+    /// default. [Fallback headers](Self::fallback_headers) still apply after the resolved header.
+    ///
+    /// Only the middleware resolves the header: without the request at hand,
+    /// [`TraceId::from_headers`](crate::TraceId::from_headers),
+    /// [`RequestContext::inject`](crate::RequestContext::inject) and
+    /// [`SetTraceIdLayer::from_config`](crate::SetTraceIdLayer::from_config) keep using
+    /// `header_name`.
+    ///
+    /// `resolver` runs on every request once the middleware's early checks passed (duplicate
+    /// middleware, [`skip_methods`](Self::skip_methods) and [`max_hops`](Self::max_hops)), before
+    /// the trace ID is read, so keep it cheap: match on the host or a path prefix, and return
+    /// [`HeaderName::from_static`] constants rather than parsing names per request.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{HeaderName, Request, request::Parts};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TRACE_ID_HEADER, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// fn tenant_header(parts: &Parts) -> HeaderName {
+    ///     match parts.uri.path() {
+    ///         path if path.starts_with("/acme/") => HeaderName::from_static("x-acme-trace"),
+    ///         _ => TRACE_ID_HEADER,
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .header_name_resolver(tenant_header)
+    ///     .echo_header(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let router = Router::new().route("/acme/orders", get(|| async {}));
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    /// let request = Request::get("/acme/orders")
+    ///     .header("x-acme-trace", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert_eq!(response.headers()["x-acme-trace"], "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// # }
+    /// ```
+    pub fn header_name_resolver(
+        mut self,
+        resolver: impl Fn(&Parts) -> HeaderName + Send + Sync + 'static,
+    ) -> Self {
+        self.config.header_name_resolver = Some(HeaderNameFn(Arc::new(resolver)));
+        self
+    }
+
+    /// Reads the trace ID from `header_names`, in order, when the trace ID header is missing, e.g.
+    /// to keep the `X-Request-Id` a proxy set rather than generating a new ID.
+    ///
+    /// The precedence becomes: the trace ID header, then each fallback header, then a newly
+    /// generated ID. Fallback values are only adopted if they're valid UUIDs, others are skipped
+    /// silently, since these headers are often set in a format of their own. An adopted value is
+    /// reported as [`TraceIdSource::Fallback`](crate::TraceIdSource::Fallback), and the header it
+    /// came from is recorded as the `trace_id.header` span field. Like the trace ID header, an
+    /// adopted value satisfies [`require_inbound`](Self::require_inbound), becomes the parent ID
+    /// in [chain mode](Self::chain), and, with a `signing_key` (`signing` feature), is only kept
+    /// with a valid signature. Fallback headers are ignored with an
+    /// [untrusted](InboundTrust::Untrusted) inbound header.
+    ///
+    /// Header names are validated like [`header_name`](Self::header_name).
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .fallback_headers(["x-request-id"])
+    ///     .echo_header(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    /// let request = Request::get("/")
+    ///     .header("x-request-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert_eq!(response.headers()["x-trace-id"], "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// # }
+    /// ```
+    pub fn fallback_headers(
+        mut self,
+        header_names: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self {
+        for header_name in header_names {
+            if let Some(header_name) = self.parse_header_name(header_name.as_ref()) {
+                self.config.fallback_headers.push(header_name);
+            }
+        }
+        self
+    }
+
+    /// Reads the correlation ID `name` from the `header_name` header into the
+    /// [`CorrelationContext`](crate::CorrelationContext), and records it on the span.
+    ///
+    /// The header name is validated like [`header_name`](Self::header_name).
+    pub fn correlation_header(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        header_name: impl AsRef<str>,
+    ) -> Self {
+        if let Some(header_name) = self.parse_header_name(header_name.as_ref()) {
+            self.config
+                .correlation_headers
+                .push((name.into(), header_name));
+        }
+        self
+    }
+
+    /// Parses `header_name`, keeping the error for [`build`](Self::build) if it's invalid.
+    fn parse_header_name(&mut self, header_name: &str) -> Option<HeaderName> {
+        HeaderName::from_bytes(header_name.as_bytes())
+            .map_err(|source| {
+                self.error.get_or_insert(ConfigError::InvalidHeaderName {
+                    name: header_name.to_owned(),
+                    source,
+                });
+            })
+            .ok()
+    }
+
+    /// Computes the trace ID from the request parts, e.g. from a structured auth token, before
+    /// falling back to the header.
+    ///
+    /// The precedence is:
+    ///
+    /// 1. `source`, then [`source_async`](Self::source_async), if it returns `Some` (as
+    ///    [`TraceIdSource::Custom`](crate::TraceIdSource::Custom)),
+    /// 2. the trace ID header (as [`TraceIdSource::Header`](crate::TraceIdSource::Header)),
+    /// 3. the [fallback headers](Self::fallback_headers) (as
+    ///    [`TraceIdSource::Fallback`](crate::TraceIdSource::Fallback)),
+    /// 4. a newly generated ID.
+    ///
+    /// [`require_inbound`](Self::require_inbound) is satisfied by any of the first three, and
+    /// [chain mode](Self::chain) makes any of them the parent of this hop. Signatures are checked on
+    /// the header and the fallback headers alike, against the same signature header: `source` is
+    /// trusted.
+    ///
+    /// ```rust
+    /// use http::request::Parts;
+    /// use trace_id_layer::{TraceId, TraceIdConfig};
+    /// use uuid::Uuid;
+    ///
+    /// // Partner tokens look like `Partner <trace_id>.<signature>`
+    /// fn from_partner_token(parts: &Parts) -> Option<TraceId> {
+    ///     let token = parts.headers.get("authorization")?.to_str().ok()?;
+    ///     let (trace_id, _) = token.strip_prefix("Partner ")?.split_once('.')?;
+    ///     Uuid::parse_str(trace_id).ok().map(TraceId::from)
+    /// }
+    ///
+    /// let config = TraceIdConfig::builder().source(from_partner_token).build().unwrap();
+    /// ```
+    pub fn source(
+        mut self,
+        source: impl Fn(&Parts) -> Option<TraceId> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.source = Some(SourceFn(Arc::new(source)));
+        self
+    }
+
+    /// Computes the trace ID asynchronously, e.g. by looking it up in a distributed cache, before
+    /// falling back to the header.
+    ///
+    /// `source` picks what it needs from the request parts synchronously, and returns a future
+    /// resolving to the trace ID. It runs after [`source`](Self::source), if that returned `None`,
+    /// with the same precedence otherwise. A `None` result, e.g. when the cache can't be reached,
+    /// falls back to the header or a newly generated ID, so the request is still served.
+    ///
+    /// The request waits for the future, so keep it short and put a timeout on remote calls. This
+    /// enables cluster-wide idempotent trace IDs, keyed by an idempotency key: get the ID stored
+    /// under the key, or store a new one if there's none.
+    ///
+    /// ```rust
+    /// use std::{collections::HashMap, sync::{Arc, Mutex}};
+    ///
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+    /// use uuid::Uuid;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// // Stands in for a shared cache
+    /// let cache = Arc::new(Mutex::new(HashMap::<String, Uuid>::new()));
+    /// let config = TraceIdConfig::builder()
+    ///     .source_async(move |parts| {
+    ///         let key = parts.headers.get("idempotency-key").and_then(|v| v.to_str().ok()).map(str::to_owned);
+    ///         let cache = cache.clone();
+    ///         async move {
+    ///             let mut cache = cache.lock().unwrap();
+    ///             Some(TraceId::from(*cache.entry(key?).or_insert_with(Uuid::now_v7)))
+    ///         }
+    ///     })
+    ///     .echo_header(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let request = || Request::post("/").header("idempotency-key", "order-42").body(Body::empty()).unwrap();
+    /// let first = router.clone().oneshot(request()).await.unwrap();
+    /// let retry = router.oneshot(request()).await.unwrap();
+    /// assert_eq!(first.headers()["x-trace-id"], retry.headers()["x-trace-id"]);
+    /// # }
+    /// ```
+    ///
+    /// With Redis, e.g. the [`redis`](https://docs.rs/redis) crate, `SET NX` followed by `GET` in
+    /// a pipeline does the get-or-create atomically across instances, whichever stores the key
+    /// first wins:
+    ///
+    /// ```rust,ignore
+    /// let redis = redis::Client::open("redis://127.0.0.1/")?
+    ///     .get_multiplexed_async_connection()
+    ///     .await?;
+    /// let config = TraceIdConfig::builder()
+    ///     .source_async(move |parts| {
+    ///         let key = parts.headers.get("idempotency-key").and_then(|v| v.to_str().ok());
+    ///         let key = key.map(|key| format!("trace_id:{key}"));
+    ///         let mut redis = redis.clone();
+    ///         async move {
+    ///             let key = key?;
+    ///             let (trace_id,): (String,) = redis::pipe()
+    ///                 .cmd("SET").arg(&key).arg(Uuid::now_v7().to_string()).arg("NX").arg("EX").arg(86_400).ignore()
+    ///                 .get(&key)
+    ///                 .query_async(&mut redis)
+    ///                 .await
+    ///                 .ok()?;
+    ///             trace_id.parse().ok()
+    ///         }
+    ///     })
+    ///     .build()?;
+    /// ```
+    pub fn source_async<F>(mut self, source: impl Fn(&Parts) -> F + Send + Sync + 'static) -> Self
+    where
+        F: Future<Output = Option<TraceId>> + Send + 'static,
+    {
+        let source = move |parts: &Parts| -> SourceFuture { Box::pin(source(parts)) };
+        self.config.async_source = Some(AsyncSourceFn(Arc::new(source)));
+        self
+    }
+
+    /// Adopts a UUID an earlier middleware put in the request extensions, e.g. after extracting it
+    /// from a proprietary protocol, as the trace ID.
+    ///
+    /// A shorthand for [`source`](Self::source) reading only the extensions, with the same
+    /// precedence, and replacing any previous source. The earlier middleware must run before this
+    /// one, i.e. be added to the router after it.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, extract::Request, middleware::{self, Next}, routing::get};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+    /// use uuid::Uuid;
+    ///
+    /// #[derive(Clone)]
+    /// struct ProtocolId(Uuid);
+    ///
+    /// async fn decode_protocol(mut request: Request, next: Next) -> axum::response::Response {
+    ///     let id = Uuid::parse_str("0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e").unwrap();
+    ///     request.extensions_mut().insert(ProtocolId(id));
+    ///     next.run(request).await
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .source_from_extensions(|extensions| extensions.get::<ProtocolId>().map(|id| id.0))
+    ///     .build()
+    ///     .unwrap();
+    /// let handler = |trace_id: TraceId| async move { trace_id.to_string() };
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config)
+    ///     .layer(middleware::from_fn(decode_protocol));
+    ///
+    /// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    /// assert_eq!(body, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// # }
+    /// ```
+    pub fn source_from_extensions(
+        self,
+        source: impl Fn(&Extensions) -> Option<Uuid> + Send + Sync + 'static,
+    ) -> Self {
+        self.source(move |parts| source(&parts.extensions).map(TraceId::from))
+    }
+
+    /// Generates trace IDs as v5 UUIDs of `namespace` and the name `name` picks from the request,
+    /// e.g. a client-supplied idempotency key, so retries of a request share a trace ID.
+    ///
+    /// Requests `name` returns `None` for get a random UUIDv7 as usual. This only replaces
+    /// generation: a [`source`](Self::source) or header trace ID still takes precedence, and
+    /// [chain mode](Self::chain) hop IDs stay random.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    /// use uuid::Uuid;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .echo_header(true)
+    ///     .deterministic_v5(Uuid::NAMESPACE_URL, |parts| {
+    ///         Some(parts.headers.get("idempotency-key")?.to_str().ok()?.to_owned())
+    ///     })
+    ///     .build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let request = || Request::post("/").header("idempotency-key", "order-42").body(Body::empty()).unwrap();
+    /// let first = router.clone().oneshot(request()).await.unwrap();
+    /// let retry = router.oneshot(request()).await.unwrap();
+    /// assert_eq!(first.headers()["x-trace-id"], retry.headers()["x-trace-id"]);
+    /// # }
+    /// ```
+    pub fn deterministic_v5(
+        mut self,
+        namespace: Uuid,
+        name: impl Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.deterministic_v5 = Some((namespace, V5NameFn(Arc::new(name))));
+        self
+    }
+
+    /// Generates short, human-typable trace IDs of `len` characters, in Crockford base32 (no `I`,
+    /// `L`, `O` or `U`), e.g. `7K3QZ0TB`, and accepts them in the header besides UUIDs.
+    ///
+    /// Meant for small internal tools, where IDs are read out over the phone: short IDs collide
+    /// far sooner than UUIDs. With `n` IDs of `b = 5 * len` bits, the chance of any collision is
+    /// about `n² / 2^(b+1)`:
+    ///
+    /// | `len` | 1% collision chance after | 50% after   |
+    /// |-------|---------------------------|-------------|
+    /// | 6     | 4.6 thousand IDs          | 39 thousand |
+    /// | 8     | 150 thousand IDs          | 1.2 million |
+    /// | 10    | 4.8 million IDs           | 40 million  |
+    /// | 12    | 150 million IDs           | 1.3 billion |
+    ///
+    /// Inbound short IDs must have exactly `len` characters. They're matched case-insensitively,
+    /// reading `I`/`L` as `1` and `O` as `0`, and normalized to uppercase. `len` must be between 1
+    /// and 24, otherwise [`build`](Self::build) fails.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().short_ids(8).echo_header(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(
+    ///     Router::new().route("/", get(|trace_id: TraceId| async move { trace_id.to_string() })),
+    ///     config,
+    /// );
+    ///
+    /// let response = router.clone().oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert_eq!(response.headers()["x-trace-id"].len(), 8);
+    ///
+    /// let request = Request::get("/").header("x-trace-id", "7k3qzotb").body(Body::empty()).unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert_eq!(response.headers()["x-trace-id"], "7K3QZ0TB");
+    /// # }
+    /// ```
+    pub fn short_ids(mut self, len: usize) -> Self {
+        if (1..=short_id::MAX_LEN).contains(&len) {
+            self.config.short_ids = Some(len);
+        } else {
+            self.error
+                .get_or_insert(ConfigError::InvalidShortIdLength(len));
+        }
+        self
+    }
+
+    /// Embeds `node_id` in generated trace IDs, to tell which instance handled a request.
+    ///
+    /// The ID is written to the last 16 bits of the UUIDv7, leaving its 48-bit timestamp and 58
+    /// random bits, so it's still a standard UUIDv7 everywhere it's parsed. Read it back with
+    /// [`TraceId::node_id`](crate::TraceId::node_id). Doesn't apply to
+    /// [short IDs](Self::short_ids) or [deterministic](Self::deterministic_v5) ones. Off by
+    /// default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().node_id(42).echo_header(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// let trace_id: TraceId = response.headers()["x-trace-id"].to_str().unwrap().parse().unwrap();
+    /// assert_eq!(trace_id.node_id(), Some(42));
+    /// # }
+    /// ```
+    pub fn node_id(mut self, node_id: u16) -> Self {
+        self.config.node_id = Some(node_id);
+        self
+    }
+
+    /// Sets the clock generated UUIDv7 trace IDs take their timestamp from, e.g. to produce
+    /// predictable, ordered IDs in tests.
+    ///
+    /// Defaults to the system clock. The random bits stay random.
+    ///
+    /// ```rust
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    /// use uuid::Uuid;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .clock(|| UNIX_EPOCH + Duration::from_millis(1_700_000_000_000))
+    ///     .echo_header(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// let uuid = Uuid::parse_str(response.headers()["x-trace-id"].to_str().unwrap()).unwrap();
+    /// let (secs, _) = uuid.get_timestamp().unwrap().to_unix();
+    /// assert_eq!(secs, 1_700_000_000);
+    /// # }
+    /// ```
+    pub fn clock(mut self, now: impl Fn() -> SystemTime + Send + Sync + 'static) -> Self {
+        self.config.clock = Some(ClockFn(Arc::new(now)));
+        self.coarse_clock = None;
+        self
+    }
+
+    /// Takes the timestamp of generated UUIDv7 trace IDs from a [`CoarseClock`](crate::CoarseClock)
+    /// refreshed every `interval`, instead of calling the system clock for every request.
+    ///
+    /// Only worth it at very high request rates: see [`CoarseClock`](crate::CoarseClock) for the
+    /// precision tradeoff, and the `generate` benchmark. Replaces any [`clock`](Self::clock). The
+    /// clock's thread is started by the [build](Self::build).
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder().coarse_clock(Duration::from_millis(1)).build().unwrap();
+    /// ```
+    pub fn coarse_clock(mut self, interval: Duration) -> Self {
+        self.coarse_clock = Some(interval);
+        self
+    }
+
+    /// Sets the key the trace ID is recorded under on the `http-request` span.
+    ///
+    /// Defaults to [`SpanField::TraceId`].
+    pub fn span_field(mut self, span_field: SpanField) -> Self {
+        self.config.span_field = span_field;
+        self
+    }
+
+    /// Adds a static `key=value` attribute, such as the service name, version or region, to every
+    /// `http-request` span.
+    ///
+    /// `tracing` only allows span fields whose names are known at compile time, so all attributes
+    /// are recorded together in a single `attributes` field, in the order they were added:
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .span_attribute("service", "checkout")
+    ///     .span_attribute("region", "eu-west-1")
+    ///     .build().unwrap();
+    ///
+    /// // INFO http-request{trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e attributes=service=checkout region=eu-west-1}: ...
+    /// ```
+    pub fn span_attribute(mut self, key: impl Display, value: impl Display) -> Self {
+        let attributes = &mut self.config.span_attributes;
+        if !attributes.is_empty() {
+            attributes.push(' ');
+        }
+        attributes.push_str(&format!("{key}={value}"));
+        self
+    }
+
+    /// Records a `key=value` attribute computed from the response, such as the final content type
+    /// or a cache status set by the handler, on the `http-request` span.
+    ///
+    /// `field` runs once the response is produced, and attributes it returns `None` for are left
+    /// out. As with [`span_attribute`](Self::span_attribute), all of them are recorded together in
+    /// a single, up front declared `response_attributes` field:
+    ///
+    /// ```rust
+    /// use http::header::CONTENT_TYPE;
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .response_field("content_type", |response| {
+    ///         let content_type = response.headers().get(CONTENT_TYPE)?;
+    ///         content_type.to_str().ok().map(str::to_owned)
+    ///     })
+    ///     .response_field("cache", |response| {
+    ///         Some(response.headers().get("x-cache")?.to_str().ok()?.to_owned())
+    ///     })
+    ///     .build().unwrap();
+    ///
+    /// // INFO http-request{trace_id=0192f0c4-... outcome="success" response_attributes=content_type=text/plain cache=HIT}: ...
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn response_field(
+        mut self,
+        key: impl Into<Cow<'static, str>>,
+        field: impl Fn(&Response) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        let field = ResponseFieldFn(Arc::new(field));
+        self.config.response_fields.push((key.into(), field));
+        self
+    }
+
+    /// Creates the `http-request` span with `make_span` instead of the built-in field set,
+    /// usually declared with [`trace_id_span_fields!`](crate::trace_id_span_fields).
+    ///
+    /// The layer records the trace ID and its other fields on the span if `make_span` declared
+    /// them, see the macro for details.
+    #[cfg(feature = "tracing")]
+    pub fn span_fields(
+        mut self,
+        make_span: impl Fn(&Request<Body>) -> tracing::Span + Send + Sync + 'static,
+    ) -> Self {
+        self.config.span_fields = Some(MakeSpanFn(Arc::new(make_span)));
+        self
+    }
+
+    /// Sets how UUID trace IDs are written to response headers.
+    ///
+    /// Defaults to [`IdFormat::Hyphenated`]. Inbound headers are accepted in either format.
+    ///
+    /// This doesn't change how [`TraceId`] is displayed, which is always hyphenated: extract a
+    /// [`FormattedTraceId`] to interpolate the trace ID in this format.
+    pub fn id_format(mut self, id_format: IdFormat) -> Self {
+        self.config.id_format = id_format;
+        self
+    }
+
+    /// Echoes the trace ID back in the trace ID response header.
+    ///
+    /// Off by default.
+    pub fn echo_header(mut self, echo_header: bool) -> Self {
+        self.config.echo_header = echo_header;
+        self
+    }
+
+    /// Echoes the trace ID back like [`echo_header`](Self::echo_header), but only on responses
+    /// whose status matches `predicate`.
+    ///
+    /// Saves the header's bytes on high-volume success responses, while keeping it on errors, where
+    /// it matters for support:
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .echo_header_if(|status| status.is_client_error() || status.is_server_error())
+    ///     .build().unwrap();
+    /// let router = Router::new()
+    ///     .route("/", get(|| async {}))
+    ///     .route("/fail", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }));
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    ///
+    /// let response = router.clone().oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert!(!response.headers().contains_key("x-trace-id"));
+    ///
+    /// let response = router.oneshot(Request::get("/fail").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert!(response.headers().contains_key("x-trace-id"));
+    /// # }
+    /// ```
+    pub fn echo_header_if(
+        mut self,
+        predicate: impl Fn(StatusCode) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.echo_header = true;
+        self.config.echo_if = Some(EchoIfFn(Arc::new(predicate)));
+        self
+    }
+
+    /// Only echoes the trace ID to cross-origin callers whose `Origin` is in `origins`, so
+    /// internal correlation IDs aren't exposed to arbitrary websites, while first-party frontends
+    /// can still show them.
+    ///
+    /// This inspects the `Origin` request header, which browsers send on cross-origin requests,
+    /// and on same-origin ones other than `GET` and `HEAD`, so list the service's own origin too.
+    /// Requests without one, e.g. not from a browser, are echoed as usual. Requests from other
+    /// origins get no trace ID header. Origins are compared exactly, e.g.
+    /// `https://app.example.com`, without a trailing slash.
+    ///
+    /// For allowed origins, the header is also added to `Access-Control-Expose-Headers`, so
+    /// scripts can read it. A `tower_http` `CorsLayer` added after this middleware replaces that
+    /// header: list the trace ID header in its `expose_headers` as well. Since the response then
+    /// depends on the origin, `Vary: origin` is appended to every response, so shared caches don't
+    /// serve it to another origin.
+    ///
+    /// Only applies with [`echo_header`](Self::echo_header). The
+    /// [`Server-Timing`](Self::server_timing) entry isn't affected, since it's only readable
+    /// cross-origin with a `Timing-Allow-Origin` header anyway.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, header::{ACCESS_CONTROL_EXPOSE_HEADERS, ORIGIN, VARY}};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .echo_header(true)
+    ///     .echo_origins(["https://app.example.com"])
+    ///     .build()
+    ///     .unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let request = |origin| Request::get("/").header(ORIGIN, origin).body(Body::empty()).unwrap();
+    /// let response = router.clone().oneshot(request("https://app.example.com")).await.unwrap();
+    /// assert!(response.headers().contains_key("x-trace-id"));
+    /// assert_eq!(response.headers()[ACCESS_CONTROL_EXPOSE_HEADERS], "x-trace-id");
+    /// assert_eq!(response.headers()[VARY], "origin");
+    ///
+    /// let response = router.oneshot(request("https://evil.example")).await.unwrap();
+    /// assert!(!response.headers().contains_key("x-trace-id"));
+    /// assert_eq!(response.headers()[VARY], "origin");
+    /// # }
+    /// ```
+    pub fn echo_origins(mut self, origins: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let origins = origins
+            .into_iter()
+            .filter_map(|origin| {
+                HeaderValue::from_str(origin.as_ref())
+                    .map_err(|source| {
+                        self.error.get_or_insert(ConfigError::InvalidOrigin {
+                            origin: origin.as_ref().to_owned(),
+                            source,
+                        });
+                    })
+                    .ok()
+            })
+            .collect();
+        self.config.echo_origins = Some(origins);
+        self
+    }
+
+    /// Echoes an inbound trace ID exactly as the client sent it, rather than in the configured
+    /// [`IdFormat`], for clients comparing it byte for byte.
+    ///
+    /// Generated trace IDs, and in [chain mode](Self::chain) every trace ID, are still formatted.
+    /// Only applies with [`echo_header`](Self::echo_header). Off by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().echo_header(true).echo_verbatim(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192F0C45B8E7C3A9D2E6F1A2B3C4D5E")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert_eq!(response.headers()["x-trace-id"], "0192F0C45B8E7C3A9D2E6F1A2B3C4D5E");
+    /// # }
+    /// ```
+    pub fn echo_verbatim(mut self, echo_verbatim: bool) -> Self {
+        self.config.echo_verbatim = echo_verbatim;
+        self
+    }
+
+    /// Appends a `Server-Timing: trace;desc="<trace ID>"` entry to responses, so the trace ID
+    /// shows up in the browser devtools' Network tab. Existing `Server-Timing` entries are kept.
+    ///
+    /// Off by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let router = Router::new().route("/", get(|| async { ([("server-timing", "db;dur=53")], "") }));
+    /// let config = TraceIdConfig::builder().server_timing(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    ///
+    /// let entries: Vec<_> = response.headers().get_all("server-timing").iter().collect();
+    /// assert_eq!(entries, ["db;dur=53", "trace;desc=\"0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e\""]);
+    /// # }
+    /// ```
+    pub fn server_timing(mut self, server_timing: bool) -> Self {
+        self.config.server_timing = server_timing;
+        self
+    }
+
+    /// Rejects requests without a trace ID header instead of generating one.
+    ///
+    /// Meant for strictly-instrumented meshes, where a missing ID is a bug to surface. For
+    /// validation alone, without the rest of the middleware, see
+    /// [`RequireTraceIdLayer`](crate::RequireTraceIdLayer). Only absence is rejected: a header
+    /// that's present but invalid is handled by [`invalid_header`](Self::invalid_header).
+    /// Rejected requests get the [`missing_response`](Self::missing_response), `400 Bad Request`
+    /// by default.
+    ///
+    /// Off by default. An [untrusted](InboundTrust::Untrusted) inbound header is stripped before
+    /// it could satisfy the requirement, so combining both fails the [build](Self::build) with
+    /// [`ConfigError::RequireUntrustedInbound`].
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().require_inbound(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let response = router
+    ///     .oneshot(Request::get("/").body(Body::empty()).unwrap())
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    /// # }
+    /// ```
+    pub fn require_inbound(mut self, require_inbound: bool) -> Self {
+        self.config.require_inbound = require_inbound;
+        self
+    }
+
+    /// Like [`require_inbound`](Self::require_inbound), but only for requests with one of
+    /// `methods`, e.g. writes that must carry a client-supplied trace ID for audit, while trace IDs
+    /// are still generated for other requests.
+    ///
+    /// Requests with a listed method and no trace ID header get the
+    /// [`missing_response`](Self::missing_response), `400 Bad Request` with a message naming the
+    /// missing header by default. Like `require_inbound`, it can't be combined with an
+    /// [untrusted](InboundTrust::Untrusted) inbound header.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Method, Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .require_inbound_for_methods([Method::POST, Method::PUT, Method::DELETE])
+    ///     .build()
+    ///     .unwrap();
+    /// let router = Router::new().route("/", get(|| async {}).post(|| async {}));
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    ///
+    /// let response = router.clone().oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::OK);
+    ///
+    /// let response = router.oneshot(Request::post("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    /// # }
+    /// ```
+    pub fn require_inbound_for_methods(
+        mut self,
+        methods: impl IntoIterator<Item = Method>,
+    ) -> Self {
+        self.config.require_inbound_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Sets the response for requests rejected by [`require_inbound`](Self::require_inbound) or
+    /// [`require_inbound_for_methods`](Self::require_inbound_for_methods).
+    ///
+    /// Defaults to `400 Bad Request` with a message naming the missing header.
+    pub fn missing_response(
+        mut self,
+        status: StatusCode,
+        message: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.config.missing_response = Some((status, message.into()));
+        self
+    }
+
+    /// Sets how requests with a trace ID header that's present but invalid are handled, whether
+    /// it isn't visible ASCII or doesn't parse.
+    ///
+    /// Defaults to [`InvalidHeader::Regenerate`].
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{HeaderValue, Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{InvalidHeader, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .invalid_header(InvalidHeader::Reject)
+    ///     .log_invalid_bytes(true)
+    ///     .build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// for value in [HeaderValue::from_static("not-a-uuid"), HeaderValue::from_bytes(b"\xfa\xfb").unwrap()] {
+    ///     let request = Request::get("/").header("x-trace-id", value).body(Body::empty()).unwrap();
+    ///     let response = router.clone().oneshot(request).await.unwrap();
+    ///     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    /// }
+    /// # }
+    /// ```
+    pub fn invalid_header(mut self, invalid_header: InvalidHeader) -> Self {
+        self.config.invalid_header = invalid_header;
+        self
+    }
+
+    /// Sets whether inbound trace ID headers are trusted.
+    ///
+    /// Defaults to [`InboundTrust::Trusted`], assuming callers are internal services. On the
+    /// public edge, use [`InboundTrust::Untrusted`] so clients can't inject arbitrary IDs into
+    /// your logs: the header is removed and a trace ID always generated. To accept trace IDs
+    /// issued by your own edge instead, see `signing_key` (`signing` feature).
+    ///
+    /// Since the header is stripped, an untrusted header can't satisfy
+    /// [`require_inbound`](Self::require_inbound) or
+    /// [`require_inbound_for_methods`](Self::require_inbound_for_methods): combining them fails
+    /// the [build](Self::build) with [`ConfigError::RequireUntrustedInbound`].
+    ///
+    /// ```rust
+    /// use trace_id_layer::{ConfigError, InboundTrust, TraceIdConfig};
+    ///
+    /// let error = TraceIdConfig::builder()
+    ///     .inbound_trust(InboundTrust::Untrusted)
+    ///     .require_inbound(true)
+    ///     .build()
+    ///     .unwrap_err();
+    /// assert!(matches!(error, ConfigError::RequireUntrustedInbound));
+    /// ```
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{HeaderMap, Request};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{InboundTrust, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .inbound_trust(InboundTrust::Untrusted)
+    ///     .echo_header(true)
+    ///     .build()
+    ///     .unwrap();
+    /// // The handler doesn't see the client's header either
+    /// let handler = |headers: HeaderMap| async move { assert!(!headers.contains_key("x-trace-id")) };
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert!(response.status().is_success());
+    /// assert_ne!(response.headers()["x-trace-id"], "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// # }
+    /// ```
+    pub fn inbound_trust(mut self, inbound_trust: InboundTrust) -> Self {
+        self.config.inbound_trust = inbound_trust;
+        self
+    }
+
+    /// Logs at most `per_minute` invalid trace ID headers per minute, so a misbehaving client can't
+    /// flood the logs.
+    ///
+    /// Headers past the limit are still handled as configured with
+    /// [`invalid_header`](Self::invalid_header), only their log line is dropped. How many were
+    /// dropped is logged with the first invalid header of a later minute. Defaults to 100, shared
+    /// by clones of the config.
+    pub fn invalid_header_log_limit(mut self, per_minute: u32) -> Self {
+        self.config.invalid_header_log = LogThrottle::new(per_minute);
+        self
+    }
+
+    /// Adds the raw bytes of invalid trace ID headers, hex-encoded, to the log event reporting
+    /// them, as the `raw` field.
+    ///
+    /// Useful to debug clients sending opaque or non-UTF-8 values. Off by default, since the value
+    /// is client-controlled.
+    pub fn log_invalid_bytes(mut self, log_invalid_bytes: bool) -> Self {
+        self.config.log_invalid_bytes = log_invalid_bytes;
+        self
+    }
+
+    /// Enables chain mode, for per-hop trace IDs.
+    ///
+    /// Instead of adopting the inbound trace ID, every request gets a newly generated
+    /// [`TraceId`] for this hop, and the inbound one is kept as its [`ParentId`](crate::ParentId). Both are recorded
+    /// on the span, as `trace_id` and `parent_id`. Forward this hop's `TraceId` to downstream
+    /// services so they record it as their parent.
+    ///
+    /// Off by default: the inbound trace ID is used as-is.
+    pub fn chain(mut self, chain: bool) -> Self {
+        self.config.chain = chain;
+        self
+    }
+
+    /// Runs the middleware in dry-run mode, to observe it before rolling it out.
+    ///
+    /// Trace IDs are still read, validated and generated, and what would happen is logged (the
+    /// assigned trace ID, and requests that would be rejected), but requests and responses go
+    /// through untouched: no request is rejected, no response header is set, and nothing is
+    /// inserted into the request extensions. The [`TraceId`] extractor, and the others relying on
+    /// the middleware, therefore fail in this mode. Off by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .require_inbound(true)
+    ///     .echo_header(true)
+    ///     .dry_run(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::OK);
+    /// assert!(!response.headers().contains_key("x-trace-id"));
+    /// # }
+    /// ```
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    /// Numbers requests with a per-process [`RequestSeq`](crate::RequestSeq), recorded as the
+    /// `request_seq` span field and available as an extractor.
+    ///
+    /// Off by default.
+    pub fn request_seq(mut self, request_seq: bool) -> Self {
+        self.config.request_seq = request_seq;
+        self
+    }
+
+    /// Reads the client's idempotency key from the `header_name` header (usually
+    /// `idempotency-key`) into an [`IdempotencyKey`](crate::IdempotencyKey), recorded as the
+    /// `idempotency_key` span field and available as an extractor, so retries can be told apart
+    /// from new requests in logs.
+    ///
+    /// To also give retries the same trace ID, derive it from the key with
+    /// [`deterministic_v5`](Self::deterministic_v5). The header name is validated like
+    /// [`header_name`](Self::header_name). Off by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{IdempotencyKey, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().idempotency_header("idempotency-key").build().unwrap();
+    /// let handler = |key: Option<IdempotencyKey>| async move { key.map(|key| key.to_string()).unwrap_or_default() };
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config);
+    ///
+    /// let request = Request::get("/").header("idempotency-key", "order-42").body(Body::empty()).unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    /// assert_eq!(body, "order-42");
+    /// # }
+    /// ```
+    pub fn idempotency_header(mut self, header_name: impl AsRef<str>) -> Self {
+        if let Some(header_name) = self.parse_header_name(header_name.as_ref()) {
+            self.config.idempotency_header = Some(header_name);
+        }
+        self
+    }
+
+    /// Counts the services a request went through in the
+    /// [`x-trace-hops`](crate::TRACE_HOPS_HEADER) header, to detect proxy loops and measure
+    /// fan-out depth.
+    ///
+    /// The middleware reads the inbound count (0 if missing or invalid) and adds this hop. The
+    /// [`HopCount`](crate::HopCount) is recorded as the `hops` span field, available as an
+    /// extractor, and sent downstream by [`RequestContext::inject`](crate::RequestContext::inject).
+    /// Requests over [`max_hops`](Self::max_hops), [`DEFAULT_MAX_HOPS`](crate::DEFAULT_MAX_HOPS)
+    /// (32) by default, are rejected with `508 Loop Detected`. Off by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{HopCount, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().hop_count(true).build().unwrap();
+    /// let handler = |hops: Option<HopCount>| async move { hops.unwrap().to_string() };
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config);
+    ///
+    /// let request = |hops: u32| Request::get("/").header("x-trace-hops", hops).body(Body::empty()).unwrap();
+    /// let response = router.clone().oneshot(request(2)).await.unwrap();
+    /// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    /// assert_eq!(body, "3");
+    ///
+    /// let response = router.oneshot(request(32)).await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::LOOP_DETECTED);
+    /// # }
+    /// ```
+    pub fn hop_count(mut self, hop_count: bool) -> Self {
+        self.config.max_hops = hop_count.then_some(DEFAULT_MAX_HOPS);
+        self
+    }
+
+    /// Enables the [hop count](Self::hop_count), rejecting requests that went through more than
+    /// `max_hops` services, this one included.
+    pub fn max_hops(mut self, max_hops: u32) -> Self {
+        self.config.max_hops = Some(max_hops);
+        self
+    }
+
+    /// Parses the W3C `baggage` header into a [`Baggage`](crate::Baggage), available as an
+    /// extractor.
+    ///
+    /// Off by default. Requires the `baggage` feature.
+    #[cfg(feature = "baggage")]
+    pub fn baggage(mut self, baggage: bool) -> Self {
+        self.config.baggage = baggage;
+        self
+    }
+
+    /// Keeps the W3C `traceparent` and `tracestate` headers as a
+    /// [`TraceContext`](crate::TraceContext), available as an optional extractor and forwarded by
+    /// [`RequestContext::inject`](crate::RequestContext::inject).
+    ///
+    /// Off by default. Requires the `traceparent` feature.
+    #[cfg(feature = "traceparent")]
+    pub fn trace_context(mut self, trace_context: bool) -> Self {
+        self.config.trace_context = trace_context;
+        self
+    }
+
+    /// Logs trace IDs sent in request trailers, as some gRPC-Web gateways and chunked clients do.
+    ///
+    /// Trailers only arrive after the request body has been read, long after the `http-request`
+    /// span was created and the [`TraceId`] was handed to the handler, so a trailer can't
+    /// *become* the request's trace ID. Instead, when the handler reads the body to the end, a
+    /// trailer trace ID is logged within the span, so the two IDs can be correlated. Handlers
+    /// that don't consume the body never see the trailers.
+    ///
+    /// Off by default.
+    pub fn log_trailer_trace_id(mut self, log_trailer_trace_id: bool) -> Self {
+        self.config.trailer_trace_id = log_trailer_trace_id;
+        self
+    }
+
+    /// Sets the level of the "stream closed" log line, emitted with the trace ID once the response
+    /// body has been fully streamed.
+    ///
+    /// Raise it to correlate SSE/long-poll disconnect timing per trace. Defaults to `DEBUG`.
+    #[cfg(feature = "tracing")]
+    pub fn eos_level(mut self, eos_level: tracing::Level) -> Self {
+        self.config.eos_level = eos_level;
+        self
+    }
+
+    /// Handles the end of the response stream within the `http-request` span, so a streamed
+    /// body's outcome is part of the request's span rather than logged after it.
+    ///
+    /// The span always stays open until the body has been fully streamed, but by default the
+    /// "stream closed" line is logged outside it, with the trace ID as a field. With this option,
+    /// it's logged within the span, along with the number of bytes streamed, and the span records
+    /// them and the stream duration as the `body.bytes` and `body.duration_ms` fields before it
+    /// closes. Logs of a handler's streaming phase, e.g. from a `Stream` producing SSE events,
+    /// are within the span either way.
+    ///
+    /// It matters most for SSE and large downloads, where the response head is sent right away,
+    /// so `on_response` and its latency only cover the handler, while the stream can run for
+    /// minutes, or fail, long after. Short, fully buffered responses see no difference. It costs
+    /// a body wrapper and a middleware layer per request, and isn't used with an
+    /// [access log](Self::access_log), which replaces the "stream closed" line.
+    ///
+    /// ```rust,no_run
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder().span_covers_body(true).build().unwrap();
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn span_covers_body(mut self, span_covers_body: bool) -> Self {
+        self.config.span_covers_body = span_covers_body;
+        self
+    }
+
+    /// Logs a link to the request's logs or trace at `DEBUG` when the request is received, as the
+    /// `url` field, built from `template` with [`TraceId::log_url`].
+    ///
+    /// Off by default.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .log_url("https://logs.example.com/search?q={id}")
+    ///     .build().unwrap();
+    ///
+    /// // DEBUG http-request{trace_id=0192f0c4-...}: trace_id_layer::span: Request logs url=https://logs.example.com/search?q=0192f0c4-...
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn log_url(mut self, template: impl Into<Cow<'static, str>>) -> Self {
+        self.config.log_url = Some(template.into());
+        self
+    }
+
+    /// Records the request's HTTP version (`HTTP/1.1`, `HTTP/2.0`, ...) as the `http.version` span
+    /// field, to diagnose protocol-specific client issues.
+    ///
+    /// The negotiated ALPN protocol isn't recorded: `axum::serve` doesn't expose TLS details to
+    /// the request, and the HTTP version already tells `h2` apart from `http/1.1`. Off by default.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder().record_http_version(true).build().unwrap();
+    ///
+    /// // INFO http-request{trace_id=0192f0c4-... http.version=HTTP/2.0}: ...
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn record_http_version(mut self, record_http_version: bool) -> Self {
+        self.config.record_http_version = record_http_version;
+        self
+    }
+
+    /// Records how long ago the inbound trace ID was generated, in milliseconds, as the
+    /// `inbound_age_ms` span field, to spot upstream queuing delays and clock skew.
+    ///
+    /// The age is the local time (from the [clock](Self::clock)) minus the
+    /// [timestamp](TraceId::timestamp) embedded in the inbound ID, i.e. the header trace ID, or
+    /// the [`ParentId`](crate::ParentId) in [chain mode](Self::chain). It covers the whole way from
+    /// the first service to this one, plus the difference between their clocks: a negative age
+    /// means the upstream clock is ahead. Only recorded for UUIDv7 inbound IDs. Off by default.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder().record_inbound_age(true).build().unwrap();
+    ///
+    /// // INFO http-request{trace_id=0192f0c4-... inbound_age_ms=42}: ...
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn record_inbound_age(mut self, record_inbound_age: bool) -> Self {
+        self.config.record_inbound_age = record_inbound_age;
+        self
+    }
+
+    /// Logs exactly one structured access log line per request, at `level`, instead of the
+    /// built-in request lifecycle lines (request received, response generated, server error,
+    /// stream closed).
+    ///
+    /// The line is logged once the response is produced, with the [fields](Self::access_log_fields)
+    /// `trace_id`, `method`, `path`, `status`, `latency` and `bytes` (the exact response body size,
+    /// left out for streamed bodies), and optionally `resource_bytes`. Diagnostics such as invalid
+    /// trace ID headers or cancelled requests are still logged. Off by default.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder().access_log(tracing::Level::INFO).build().unwrap();
+    ///
+    /// // INFO trace_id_layer::span: Request completed trace_id=0192f0c4-... method=GET path="/users" status=200 latency=1.2ms bytes=42
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn access_log(mut self, level: tracing::Level) -> Self {
+        self.config.access_log = Some(level);
+        self
+    }
+
+    /// Sets the fields of the [access log](Self::access_log) line. Defaults to all of them but
+    /// [`AccessLogField::ResourceBytes`] and [`AccessLogField::RequestId`].
+    ///
+    /// ```rust
+    /// use trace_id_layer::{AccessLogField, TraceIdConfig};
+    ///
+    /// // For range requests, log the transferred bytes along with the full resource size
+    /// let config = TraceIdConfig::builder()
+    ///     .access_log(tracing::Level::INFO)
+    ///     .access_log_fields([
+    ///         AccessLogField::TraceId,
+    ///         AccessLogField::Path,
+    ///         AccessLogField::Status,
+    ///         AccessLogField::Bytes,
+    ///         AccessLogField::ResourceBytes,
+    ///     ])
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // INFO trace_id_layer::span: Request completed trace_id=0192f0c4-... path="/video" status=206 bytes=1024 resource_bytes=1048576
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn access_log_fields(mut self, fields: impl IntoIterator<Item = AccessLogField>) -> Self {
+        self.config.access_log_fields = fields.into_iter().collect();
+        self
+    }
+
+    /// Logs the per-request lines of requests with these methods, i.e. the request received line
+    /// and the [access log](Self::access_log) line, at `DEBUG` rather than `INFO`, e.g. for
+    /// high-volume CORS preflight `OPTIONS` and `HEAD` requests.
+    ///
+    /// These requests still get a trace ID and an `http-request` span, and errors are still logged
+    /// as usual: only the noise goes. Use [`skip_methods`](Self::skip_methods) to leave them out
+    /// altogether. None by default.
+    ///
+    /// ```rust
+    /// use http::Method;
+    /// use trace_id_layer::TraceIdConfig;
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .quiet_methods([Method::OPTIONS, Method::HEAD])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn quiet_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.config.quiet_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Passes requests with these methods through untouched: no trace ID is read or generated, no
+    /// response header is set, and no `http-request` span is created or logged.
+    ///
+    /// For methods that are never interesting, such as CORS preflight `OPTIONS` requests answered
+    /// by a `CorsLayer` inside this middleware. Handlers extracting a [`TraceId`] for these methods
+    /// are rejected with [`MissingTraceId`](crate::MissingTraceId), so prefer `quiet_methods`
+    /// (`tracing` feature) where trace IDs are still needed. None by default.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Method, Request};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .echo_header(true)
+    ///     .skip_methods([Method::OPTIONS])
+    ///     .build()
+    ///     .unwrap();
+    /// let router = Router::new().route("/", get(|| async {}).options(|| async {}));
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    ///
+    /// let request = Request::options("/").body(Body::empty()).unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert!(!response.headers().contains_key("x-trace-id"));
+    /// # }
+    /// ```
+    pub fn skip_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.config.skip_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Sets the trace ID in the [`log-mdc`](https://docs.rs/log-mdc) context while the request is
+    /// handled, under the [`SpanField`] key (`trace_id` by default).
+    ///
+    /// This is for code logging through the `log` crate rather than `tracing`, with appenders that
+    /// read the MDC, such as `log4rs`' `{X(trace_id)}` pattern or JSON encoders. The MDC is
+    /// thread-local, while a request future can move between worker threads, so the value is set
+    /// before every poll of the request future and the previous value restored right after. It
+    /// never leaks into other tasks, even if the request is cancelled midway. Work moved off the
+    /// request future (e.g. `tokio::spawn`) doesn't see it.
+    ///
+    /// Off by default. Requires the `log-mdc` feature.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// async fn handler() -> String {
+    ///     tokio::task::yield_now().await;
+    ///     log_mdc::get("trace_id", |trace_id| trace_id.unwrap().to_owned())
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder().log_mdc(true).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    /// assert_eq!(body, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// assert!(log_mdc::get("trace_id", |trace_id| trace_id.is_none()));
+    /// # }
+    /// ```
+    #[cfg(feature = "log-mdc")]
+    pub fn log_mdc(mut self, log_mdc: bool) -> Self {
+        self.config.log_mdc = log_mdc;
+        self
+    }
+
+    /// Makes a child of `logger` carrying the trace ID, under the [`SpanField`] key (`trace_id` by
+    /// default), the [`slog-scope`](https://docs.rs/slog-scope) logger while the request is
+    /// handled.
+    ///
+    /// This is for code logging through `slog`, e.g. with `slog_scope::info!`, in services
+    /// migrating between logging stacks. Like [`log_mdc`](Self::log_mdc), the scope is
+    /// thread-local, so it's entered for every poll of the request future and left right after:
+    /// it never leaks into other tasks, even if the request is cancelled midway, and work moved
+    /// off the request future (e.g. `tokio::spawn`) doesn't see it.
+    ///
+    /// Off by default. Requires the `slog` feature.
+    ///
+    /// ```rust
+    /// # use std::sync::{Arc, Mutex};
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # struct Capture(Arc<Mutex<Vec<String>>>);
+    /// # impl slog::Drain for Capture {
+    /// #     type Ok = ();
+    /// #     type Err = slog::Never;
+    /// #     fn log(&self, record: &slog::Record, values: &slog::OwnedKVList) -> Result<(), slog::Never> {
+    /// #         struct Line(String);
+    /// #         impl slog::Serializer for Line {
+    /// #             fn emit_arguments(&mut self, key: slog::Key, value: &std::fmt::Arguments) -> slog::Result {
+    /// #                 self.0 += &format!(" {key}={value}");
+    /// #                 Ok(())
+    /// #             }
+    /// #         }
+    /// #         let mut line = Line(record.msg().to_string());
+    /// #         slog::KV::serialize(values, record, &mut line).unwrap();
+    /// #         self.0.lock().unwrap().push(line.0);
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// async fn handler() {
+    ///     tokio::task::yield_now().await;
+    ///     slog_scope::info!("loading user");
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// # let lines = Arc::new(Mutex::new(Vec::new()));
+    /// # let drain = Capture(lines.clone());
+    /// let logger = slog::Logger::root(drain, slog::o!());
+    /// let config = TraceIdConfig::builder().slog_scope(logger).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(handler)), config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// router.oneshot(request).await.unwrap();
+    ///
+    /// // loading user trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e
+    /// # assert_eq!(*lines.lock().unwrap(), ["loading user trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"]);
+    /// # }
+    /// ```
+    #[cfg(feature = "slog")]
+    pub fn slog_scope(mut self, logger: slog::Logger) -> Self {
+        self.config.slog_logger = Some(logger);
+        self
+    }
+
+    /// Signs trace IDs with HMAC-SHA256 under `key`, so IDs forged by clients are not trusted.
+    ///
+    /// This is a security feature for deployments where only your own edge should issue trace
+    /// IDs. Inbound trace IDs are only accepted with a valid hex-encoded signature in the
+    /// [`TRACE_ID_SIG_HEADER`](crate::TRACE_ID_SIG_HEADER) (`x-trace-id-sig`); otherwise a new ID
+    /// is generated, as for a missing header. This includes IDs read from the
+    /// [fallback headers](Self::fallback_headers), which a proxy rather than your edge usually
+    /// sets. Every response carries the signature of its trace ID, to be sent back alongside it
+    /// (combine with [`echo_header`](Self::echo_header) so the client also gets the ID).
+    ///
+    /// Signatures are checked in constant time. The key is never logged, and it's the caller's job
+    /// to keep it secret and rotate it. Requires the `signing` feature.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let config = TraceIdConfig::builder()
+    ///     .echo_header(true)
+    ///     .signing_key(b"secret")
+    ///     .build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(
+    ///     Router::new().route("/", get(|trace_id: TraceId| async move { trace_id.to_string() })),
+    ///     config,
+    /// );
+    ///
+    /// // An unsigned trace ID is replaced
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.clone().oneshot(request).await.unwrap();
+    /// let trace_id = response.headers()["x-trace-id"].clone();
+    /// let signature = response.headers()["x-trace-id-sig"].clone();
+    /// assert_ne!(trace_id, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    ///
+    /// // The issued one is accepted with its signature
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", trace_id.clone())
+    ///     .header("x-trace-id-sig", signature)
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// let response = router.oneshot(request).await.unwrap();
+    /// assert_eq!(response.headers()["x-trace-id"], trace_id);
+    /// # }
+    /// ```
+    #[cfg(feature = "signing")]
+    pub fn signing_key(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.config.signing_key = Some(crate::signing::SigningKey::new(key.as_ref()));
+        self
+    }
+
+    /// Records request count and duration in `metrics`, with the trace ID as exemplar.
+    ///
+    /// See the [`metrics`](crate::metrics) module. Requires the `prometheus-exemplars` feature.
+    #[cfg(feature = "prometheus-exemplars")]
+    pub fn metrics(mut self, metrics: crate::metrics::RequestMetrics) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    /// Calls `callback` with an [`AuditRecord`](crate::AuditRecord) once each request has a
+    /// response, for audit or event pipelines that need one structured record per request.
+    ///
+    /// Errors turned into responses are included, with their status. The callback runs inline on
+    /// the task handling the request, possibly on any runtime thread, hence the `Send + Sync`
+    /// bounds: keep it quick, and hand slow work (e.g. a network write) to a channel or a spawned
+    /// task.
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::{Request, StatusCode};
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let records = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = records.clone();
+    /// let config = TraceIdConfig::builder()
+    ///     .on_complete(move |record| sink.lock().unwrap().push(record))
+    ///     .build()
+    ///     .unwrap();
+    /// let router = Router::new().route("/users/{id}", get(|| async { StatusCode::NOT_FOUND }));
+    /// let router = add_trace_id_middleware_with_config(router, config);
+    ///
+    /// let request = Request::get("/users/42?full=true")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// router.oneshot(request).await.unwrap();
+    ///
+    /// let record = records.lock().unwrap().pop().unwrap();
+    /// assert_eq!(record.trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// assert_eq!(record.path, "/users/42");
+    /// assert_eq!(record.status, StatusCode::NOT_FOUND);
+    /// # }
+    /// ```
+    pub fn on_complete(
+        mut self,
+        callback: impl Fn(crate::AuditRecord) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.on_complete = Some(OnCompleteFn(Arc::new(callback)));
+        self
+    }
+
+    /// Keeps the last `capacity` requests (trace ID, method, path, status and latency) in memory,
+    /// for a quick look at what happened recently with
+    /// [`recent_requests_handler`](crate::recent_requests_handler), e.g. while debugging live
+    /// without a log backend.
+    ///
+    /// Requests are recorded once their response is produced, like with
+    /// [`on_complete`](Self::on_complete), so failed ones are included. Memory is bounded by
+    /// `capacity` entries of about 100 bytes each, plus the length of their path; the oldest entry
+    /// is evicted first. It's a debugging aid, not a substitute for logging: entries are lost on
+    /// restart, and each instance only sees its own requests.
+    pub fn recent_requests(mut self, capacity: usize) -> Self {
+        self.config.recent_requests = Some(RecentRequests::new(capacity));
+        self
+    }
+
+    /// Writes the trace ID of each request to the file at `path` as it starts, for post-mortems of
+    /// crashes that leave no logs behind, such as a `SIGKILL` or the OOM killer. It complements
+    /// [`install_panic_hook`](crate::install_panic_hook), which only covers panics.
+    ///
+    /// The file is a ring of `slots` fixed-width lines, created (or truncated) when this is called,
+    /// and filled as requests come in: lines not written yet are NUL bytes. Each request
+    /// overwrites the oldest line with a hex sequence number and its trace ID, so after a crash,
+    /// the line with the highest sequence number is the last request that started, and the others
+    /// are the ones before it. Size `slots` above the peak number of concurrent
+    /// requests to find all those in flight; the file is `64 * slots` bytes. Trace IDs longer than
+    /// 46 bytes are truncated.
+    ///
+    /// It's best-effort, and trades durability for speed:
+    ///
+    /// - each request costs a single positional write to the page cache, on the request path,
+    ///   without locks nor `fsync` (platforms other than Unix and Windows seek and write under a
+    ///   lock instead). It outlives a crash of the process, not of the machine,
+    /// - the line is written before the handler runs, with no record of whether the request
+    ///   completed,
+    /// - write errors are logged once and otherwise ignored, never failing the request.
+    ///
+    /// The file is created, or truncated, by the [build](Self::build), which fails with
+    /// [`ConfigError::LastRequestFile`] if it can't be.
+    ///
+    /// ```rust
+    /// use axum::{Router, body::Body, routing::get};
+    /// use http::Request;
+    /// use tower::ServiceExt;
+    /// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let path = std::env::temp_dir().join(format!("trace_id_layer-last-request-{}", uuid::Uuid::new_v4()));
+    /// let config = TraceIdConfig::builder().last_request_file(&path, 256).build().unwrap();
+    /// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+    ///
+    /// let request = Request::get("/")
+    ///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// router.oneshot(request).await.unwrap();
+    ///
+    /// let marker = std::fs::read_to_string(&path).unwrap();
+    /// let last = marker.lines().next().unwrap().trim_end();
+    /// assert_eq!(last, "0000000000000000 0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// # std::fs::remove_file(&path).unwrap();
+    /// # }
+    /// ```
+    pub fn last_request_file(mut self, path: impl AsRef<Path>, slots: usize) -> Self {
+        self.last_request_file = Some((path.as_ref().to_owned(), slots));
+        self
+    }
+
+    /// Builds the config, failing if any option was set to an invalid value.
+    pub fn build(self) -> Result<TraceIdConfig, ConfigError> {
+        let mut config = self.config;
+        let requires_inbound = config.require_inbound || !config.require_inbound_methods.is_empty();
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if requires_inbound && config.inbound_trust == InboundTrust::Untrusted {
+            return Err(ConfigError::RequireUntrustedInbound);
+        }
+        if let Some(interval) = self.coarse_clock {
+            let clock = crate::CoarseClock::new(interval);
+            config.clock = Some(ClockFn(Arc::new(move || clock.now())));
+        }
+        if let Some((path, slots)) = self.last_request_file {
+            match LastRequestFile::create(&path, slots) {
+                Ok(file) => config.last_request_file = Some(file),
+                Err(source) => return Err(ConfigError::LastRequestFile { path, source }),
+            }
+        }
+        Ok(config)
+    }
+}
+
+type DynHeaderNameFn = dyn Fn(&Parts) -> HeaderName + Send + Sync;
+
+/// The callback set with [`TraceIdConfigBuilder::header_name_resolver`].
+#[derive(Clone)]
+pub(crate) struct HeaderNameFn(Arc<DynHeaderNameFn>);
+
+impl HeaderNameFn {
+    pub(crate) fn call(&self, parts: &Parts) -> HeaderName {
+        (self.0)(parts)
+    }
+}
+
+impl fmt::Debug for HeaderNameFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HeaderNameFn(..)")
+    }
+}
+
+type DynSourceFn = dyn Fn(&Parts) -> Option<TraceId> + Send + Sync;
+
+/// The callback set with [`TraceIdConfigBuilder::source`].
+#[derive(Clone)]
+pub(crate) struct SourceFn(Arc<DynSourceFn>);
+
+impl SourceFn {
+    pub(crate) fn call(&self, parts: &Parts) -> Option<TraceId> {
+        (self.0)(parts)
+    }
+}
+
+impl fmt::Debug for SourceFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SourceFn(..)")
+    }
+}
+
+type SourceFuture = Pin<Box<dyn Future<Output = Option<TraceId>> + Send>>;
+
+type DynAsyncSourceFn = dyn Fn(&Parts) -> SourceFuture + Send + Sync;
+
+/// The callback set with [`TraceIdConfigBuilder::source_async`].
+#[derive(Clone)]
+pub(crate) struct AsyncSourceFn(Arc<DynAsyncSourceFn>);
+
+impl AsyncSourceFn {
+    pub(crate) async fn call(&self, parts: &Parts) -> Option<TraceId> {
+        (self.0)(parts).await
+    }
+}
+
+impl fmt::Debug for AsyncSourceFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AsyncSourceFn(..)")
+    }
+}
+
+type DynEchoIfFn = dyn Fn(StatusCode) -> bool + Send + Sync;
+
+/// The predicate set with [`TraceIdConfigBuilder::echo_header_if`].
+#[derive(Clone)]
+pub(crate) struct EchoIfFn(Arc<DynEchoIfFn>);
+
+impl fmt::Debug for EchoIfFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EchoIfFn(..)")
+    }
+}
+
+type DynV5NameFn = dyn Fn(&Parts) -> Option<String> + Send + Sync;
+
+/// The name callback set with [`TraceIdConfigBuilder::deterministic_v5`].
+#[derive(Clone)]
+pub(crate) struct V5NameFn(Arc<DynV5NameFn>);
+
+impl fmt::Debug for V5NameFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("V5NameFn(..)")
+    }
+}
+
+#[cfg(feature = "tracing")]
+type DynResponseFieldFn = dyn Fn(&Response) -> Option<String> + Send + Sync;
+
+/// A callback set with [`TraceIdConfigBuilder::response_field`].
+#[cfg(feature = "tracing")]
+#[derive(Clone)]
+pub(crate) struct ResponseFieldFn(Arc<DynResponseFieldFn>);
+
+#[cfg(feature = "tracing")]
+impl fmt::Debug for ResponseFieldFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseFieldFn(..)")
+    }
+}
+
+#[cfg(feature = "tracing")]
+type DynMakeSpanFn = dyn Fn(&Request<Body>) -> tracing::Span + Send + Sync;
+
+/// The span constructor set with [`TraceIdConfigBuilder::span_fields`].
+#[cfg(feature = "tracing")]
+#[derive(Clone)]
+pub(crate) struct MakeSpanFn(Arc<DynMakeSpanFn>);
+
+#[cfg(feature = "tracing")]
+impl MakeSpanFn {
+    pub(crate) fn call(&self, request: &Request<Body>) -> tracing::Span {
+        (self.0)(request)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl fmt::Debug for MakeSpanFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MakeSpanFn(..)")
+    }
+}
+
+type DynClockFn = dyn Fn() -> SystemTime + Send + Sync;
+
+/// The clock set with [`TraceIdConfigBuilder::clock`].
+#[derive(Clone)]
+pub(crate) struct ClockFn(Arc<DynClockFn>);
+
+impl ClockFn {
+    pub(crate) fn now(&self) -> SystemTime {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for ClockFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClockFn(..)")
+    }
+}
+
+type DynOnCompleteFn = dyn Fn(crate::AuditRecord) + Send + Sync;
+
+/// The callback set with [`TraceIdConfigBuilder::on_complete`].
+#[derive(Clone)]
+pub(crate) struct OnCompleteFn(Arc<DynOnCompleteFn>);
+
+impl OnCompleteFn {
+    pub(crate) fn call(&self, record: crate::AuditRecord) {
+        (self.0)(record)
+    }
+}
+
+impl fmt::Debug for OnCompleteFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OnCompleteFn(..)")
+    }
+}
+
+/// The error returned by [`TraceIdConfigBuilder::build`] for an invalid option.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// A configured header name isn't a valid HTTP header name.
+    InvalidHeaderName {
+        name: String,
+        source: InvalidHeaderName,
+    },
+    /// The [short ID](TraceIdConfigBuilder::short_ids) length is 0 or above 24.
+    InvalidShortIdLength(usize),
+    /// An [echo origin](TraceIdConfigBuilder::echo_origins) isn't a valid header value.
+    InvalidOrigin {
+        origin: String,
+        source: InvalidHeaderValue,
+    },
+    /// The [last request file](TraceIdConfigBuilder::last_request_file) can't be created.
+    LastRequestFile { path: PathBuf, source: io::Error },
+    /// An inbound trace ID is [required](TraceIdConfigBuilder::require_inbound), but the inbound
+    /// header is [untrusted](InboundTrust::Untrusted), so it's stripped and every request would
+    /// be rejected.
+    RequireUntrustedInbound,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidHeaderName { name, .. } => {
+                write!(f, "Invalid header name: '{name}'")
+            }
+            ConfigError::InvalidShortIdLength(len) => {
+                write!(f, "Invalid short ID length: '{len}', expected 1 to 24")
+            }
+            ConfigError::InvalidOrigin { origin, .. } => write!(f, "Invalid origin: '{origin}'"),
+            ConfigError::LastRequestFile { path, .. } => {
+                write!(
+                    f,
+                    "Unable to create the last request file: '{}'",
+                    path.display()
+                )
+            }
+            ConfigError::RequireUntrustedInbound => {
+                f.write_str("An inbound trace ID can't be required from an untrusted header")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::InvalidHeaderName { source, .. } => Some(source),
+            ConfigError::InvalidShortIdLength(_) => None,
+            ConfigError::InvalidOrigin { source, .. } => Some(source),
+            ConfigError::LastRequestFile { source, .. } => Some(source),
+            ConfigError::RequireUntrustedInbound => None,
+        }
+    }
+}
+
+/// The key the trace ID is recorded under on the `http-request` span.
+///
+/// `tracing` requires span fields to be declared with names known at compile time, so the key
+/// can't be an arbitrary runtime string; it's picked from this fixed set of common schemas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpanField {
+    /// `trace_id`
+    #[default]
+    TraceId,
+    /// `traceId`
+    CamelCase,
+    /// `dd.trace_id`, for Datadog log correlation
+    Datadog,
+    /// `trace.id`, for the Elastic Common Schema
+    Ecs,
+}
+
+impl SpanField {
+    /// The field name as it appears on the span.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanField::TraceId => "trace_id",
+            SpanField::CamelCase => "traceId",
+            SpanField::Datadog => "dd.trace_id",
+            SpanField::Ecs => "trace.id",
+        }
+    }
+}
+
+/// How the middleware handles a trace ID header that's present but invalid.
+///
+/// Set with [`TraceIdConfigBuilder::invalid_header`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidHeader {
+    /// Log the invalid value and generate a new trace ID.
+    #[default]
+    Regenerate,
+    /// Log the invalid value and reject the request with `400 Bad Request`.
+    Reject,
+}
+
+/// A field of the [access log](TraceIdConfigBuilder::access_log) line.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AccessLogField {
+    TraceId,
+    Method,
+    /// The request path, without the query string.
+    Path,
+    Status,
+    /// Time from the request reaching the middleware to the response head being produced.
+    Latency,
+    /// The response body size, when known upfront.
+    Bytes,
+    /// The full size of the resource a `206 Partial Content` response is part of, from its
+    /// `Content-Range` header, as `resource_bytes`. Not logged by default.
+    ResourceBytes,
+    /// The `x-request-id` response header, as set by `tower_http`'s `PropagateRequestIdLayer`
+    /// inside this middleware, e.g. by the [`FullObservability`](crate::FullObservability)
+    /// preset, as `request_id`. Not logged by default.
+    RequestId,
+}
+
+#[cfg(feature = "tracing")]
+impl AccessLogField {
+    /// The fields logged by default.
+    const DEFAULT: [Self; 6] = [
+        Self::TraceId,
+        Self::Method,
+        Self::Path,
+        Self::Status,
+        Self::Latency,
+        Self::Bytes,
+    ];
+}
+
+/// Whether the middleware trusts the trace ID header on inbound requests.
+///
+/// Set with [`TraceIdConfigBuilder::inbound_trust`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InboundTrust {
+    /// Adopt the inbound trace ID, for services only reachable by trusted callers, e.g. within a
+    /// service mesh.
+    #[default]
+    Trusted,
+    /// Strip the inbound header from the request, so neither the middleware nor anything behind
+    /// it sees it, and always generate a trace ID, for services facing untrusted clients.
+    Untrusted,
+}
+
+/// How UUID trace IDs are written to headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IdFormat {
+    /// `0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e`
+    #[default]
+    Hyphenated,
+    /// `0192f0c45b8e7c3a9d2e6f1a2b3c4d5e`
+    Simple,
+}
+
+impl IdFormat {
+    pub(crate) fn format(&self, uuid: Uuid) -> String {
+        match self {
+            IdFormat::Hyphenated => uuid.hyphenated().to_string(),
+            IdFormat::Simple => uuid.simple().to_string(),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use axum::extract::FromRequestParts;
+use http::{Extensions, HeaderMap, HeaderValue, StatusCode, request::Parts};
+
+#[cfg(feature = "baggage")]
+use crate::Baggage;
+#[cfg(feature = "traceparent")]
+use crate::TraceContext;
+use crate::{CorrelationContext, HopCount, ParentId, TraceId, TraceIdConfig, log::error};
+
+/// Everything the middleware knows about a request's correlation, in one extension: the trace
+/// ID, its parent, the correlation IDs, with the `baggage` feature, the `Baggage` and, with the
+/// `traceparent` feature, the `TraceContext`, along with fields of your own.
+///
+/// Use it as an extractor, and [`inject`](Self::inject) it into outbound requests. To add your
+/// own fields (the authenticated user, feature flags, ...), insert them from a middleware running
+/// after this one:
+///
+/// ```rust
+/// use axum::{Router, body::Body, extract::Request, middleware::{self, Next}, routing::get};
+/// use http::HeaderMap;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{RequestContext, TraceIdConfig, add_trace_id_middleware_with_config};
+///
+/// #[derive(Clone)]
+/// struct User(String);
+///
+/// async fn authenticate(mut request: Request, next: Next) -> axum::response::Response {
+///     if let Some(context) = request.extensions_mut().get_mut::<RequestContext>() {
+///         context.insert(User("alice".to_owned()));
+///     }
+///     next.run(request).await
+/// }
+///
+/// async fn handler(context: RequestContext) -> String {
+///     // Forward the trace and correlation IDs downstream
+///     let mut outbound = HeaderMap::new();
+///     context.inject(&mut outbound);
+///
+///     let user = context.get::<User>().map_or("<anonymous>", |user| user.0.as_str());
+///     format!("{user} {}", outbound["x-tenant-id"].to_str().unwrap())
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder().correlation_header("tenant_id", "x-tenant-id").build().unwrap();
+/// let router = Router::new().route("/", get(handler)).layer(middleware::from_fn(authenticate));
+/// let router = add_trace_id_middleware_with_config(router, config);
+///
+/// let request = Request::get("/").header("x-tenant-id", "acme").body(Body::empty()).unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, "alice acme");
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    parent_id: Option<ParentId>,
+    correlation: CorrelationContext,
+    #[cfg(feature = "baggage")]
+    baggage: Option<Baggage>,
+    #[cfg(feature = "traceparent")]
+    trace_context: Option<TraceContext>,
+    hops: Option<HopCount>,
+    config: Arc<TraceIdConfig>,
+    extensions: Extensions,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        parent_id: Option<ParentId>,
+        correlation: CorrelationContext,
+        #[cfg(feature = "baggage")] baggage: Option<Baggage>,
+        #[cfg(feature = "traceparent")] trace_context: Option<TraceContext>,
+        hops: Option<HopCount>,
+        config: Arc<TraceIdConfig>,
+    ) -> Self {
+        Self {
+            parent_id,
+            correlation,
+            #[cfg(feature = "baggage")]
+            baggage,
+            #[cfg(feature = "traceparent")]
+            trace_context,
+            hops,
+            config,
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// The trace ID of the request.
+    pub fn trace_id(&self) -> &TraceId {
+        self.correlation.trace_id()
+    }
+
+    /// The inbound trace ID, in [chain mode](crate::TraceIdConfigBuilder::chain).
+    pub fn parent_id(&self) -> Option<&ParentId> {
+        self.parent_id.as_ref()
+    }
+
+    /// The correlation IDs the request carried.
+    pub fn correlation(&self) -> &CorrelationContext {
+        &self.correlation
+    }
+
+    /// The request's baggage, when enabled with
+    /// [`baggage`](crate::TraceIdConfigBuilder::baggage).
+    #[cfg(feature = "baggage")]
+    pub fn baggage(&self) -> Option<&Baggage> {
+        self.baggage.as_ref()
+    }
+
+    /// The request's W3C trace context, when enabled with
+    /// [`trace_context`](crate::TraceIdConfigBuilder::trace_context) and sent.
+    #[cfg(feature = "traceparent")]
+    pub fn trace_context(&self) -> Option<&TraceContext> {
+        self.trace_context.as_ref()
+    }
+
+    /// The request's hop count, when enabled with
+    /// [`hop_count`](crate::TraceIdConfigBuilder::hop_count).
+    pub fn hops(&self) -> Option<HopCount> {
+        self.hops
+    }
+
+    /// Your field of type `T`, if one was [inserted](Self::insert).
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions.get()
+    }
+
+    /// Adds a field of your own, returning the previous one of type `T`, if any.
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.extensions.insert(value)
+    }
+
+    /// The trace ID, parent ID and correlation IDs as `name=value` pairs, for logging.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, String)> {
+        let trace_id = ("trace_id", self.trace_id().to_string());
+        let parent_id = self
+            .parent_id
+            .as_ref()
+            .map(|parent_id| ("parent_id", parent_id.to_string()));
+        let correlation = self
+            .correlation
+            .iter()
+            .map(|(name, value)| (name, value.to_owned()));
+        std::iter::once(trace_id)
+            .chain(parent_id)
+            .chain(correlation)
+    }
+
+    /// Sets the trace ID header, the correlation ID headers, the hop count header when enabled,
+    /// with the `baggage` feature, the `baggage` header and, with the `traceparent` feature, the
+    /// `traceparent` and `tracestate` headers in `headers` for an outbound request, the way this
+    /// middleware reads them.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        match HeaderValue::try_from(self.config.render(self.trace_id())) {
+            Ok(value) => {
+                headers.insert(self.config.header_name.clone(), value);
+            }
+            Err(e) => error!(error = ?e, "Unable to convert trace_id to a header value"),
+        }
+        for (name, header_name) in &self.config.correlation_headers {
+            let value = self.correlation.get(name).map(HeaderValue::try_from);
+            if let Some(Ok(value)) = value {
+                headers.insert(header_name.clone(), value);
+            }
+        }
+        if let Some(hops) = &self.hops {
+            hops.inject(headers);
+        }
+        #[cfg(feature = "baggage")]
+        if let Some(baggage) = &self.baggage {
+            baggage.inject(headers);
+        }
+        #[cfg(feature = "traceparent")]
+        if let Some(trace_context) = &self.trace_context {
+            trace_context.inject(headers);
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestContext
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "RequestContext extension missing. Did you apply add_trace_id_middleware?";
+        parts
+            .extensions
+            .get::<RequestContext>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
@@ -0,0 +1,89 @@
+//! Framework-agnostic trace ID logic.
+//!
+//! Everything here works on plain [`http`] types, so it can back an adapter for any framework
+//! built on them (`actix-web`, `poem`, ...). It covers the defaults only: the trace ID header, its
+//! parsing and UUIDv7 generation. The axum middleware shares these building blocks, but layers
+//! the [configured](crate::TraceIdConfig) behavior on top, such as fallback headers, signing and
+//! custom sources.
+//!
+//! ```rust
+//! use http::HeaderMap;
+//! use trace_id_layer::{TraceIdSource, core};
+//!
+//! let mut headers = HeaderMap::new();
+//! let (_, source) = core::extract_or_generate(&headers);
+//! assert_eq!(source, TraceIdSource::Generated);
+//!
+//! headers.insert(core::TRACE_ID_HEADER, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap());
+//! let (trace_id, source) = core::extract_or_generate(&headers);
+//! assert_eq!(source, TraceIdSource::Header);
+//! assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+//! ```
+
+use http::{HeaderMap, HeaderName};
+use uuid::Uuid;
+
+use crate::{IdFormat, ParseTraceIdError, TraceId, log::error};
+
+/// The default header trace IDs are read from.
+pub const TRACE_ID_HEADER: HeaderName = HeaderName::from_static("x-trace-id");
+
+/// Where the [`TraceId`] for a request came from.
+///
+/// Inserted into the request extensions alongside the [`TraceId`], so the decision is made once
+/// during injection and never re-derived from the headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceIdSource {
+    /// The ID was read from the inbound trace ID header.
+    Header,
+    /// The trace ID header was missing, and the ID was read from one of the
+    /// [`fallback_headers`](crate::TraceIdConfigBuilder::fallback_headers).
+    Fallback,
+    /// The ID was supplied by the [`source`](crate::TraceIdConfigBuilder::source) or
+    /// [`source_async`](crate::TraceIdConfigBuilder::source_async) callback.
+    Custom,
+    /// No usable header was present, so a new ID was generated.
+    Generated,
+}
+
+/// Reads the trace ID from the [`TRACE_ID_HEADER`].
+///
+/// Returns `Ok(None)` if the header is missing, and an error if it's present but invalid.
+pub fn extract(headers: &HeaderMap) -> Result<Option<TraceId>, ParseTraceIdError> {
+    extract_header(headers, &TRACE_ID_HEADER)
+}
+
+/// Same as [`extract`], but reads the trace ID from a custom header.
+pub fn extract_header(
+    headers: &HeaderMap,
+    header_name: &HeaderName,
+) -> Result<Option<TraceId>, ParseTraceIdError> {
+    headers.get(header_name).map(TraceId::try_from).transpose()
+}
+
+/// Generates a new trace ID (a UUIDv7).
+pub fn generate() -> TraceId {
+    TraceId::from(Uuid::now_v7())
+}
+
+/// Formats `uuid` the way the middleware writes trace IDs to headers by default.
+///
+/// See [`TraceIdConfig::format_trace_id`](crate::TraceIdConfig::format_trace_id) for a
+/// configured [`IdFormat`].
+pub fn format_trace_id(uuid: Uuid) -> String {
+    IdFormat::default().format(uuid)
+}
+
+/// Reads the trace ID from `headers`, generating one if it's missing or invalid.
+///
+/// Invalid values are logged and treated as absent.
+pub fn extract_or_generate(headers: &HeaderMap) -> (TraceId, TraceIdSource) {
+    match extract(headers) {
+        Ok(Some(trace_id)) => (trace_id, TraceIdSource::Header),
+        Ok(None) => (generate(), TraceIdSource::Generated),
+        Err(e) => {
+            error!(error = %e, "Ignoring invalid inbound trace_id");
+            (generate(), TraceIdSource::Generated)
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::borrow::Cow;
+
+use axum::extract::FromRequestParts;
+use http::{HeaderMap, HeaderName, StatusCode, request::Parts};
+
+use crate::{TraceId, log::error};
+
+/// The trace ID of a request, along with the other correlation IDs it carries (request, tenant,
+/// session, ...).
+///
+/// Declare the headers they're read from with
+/// [`correlation_header`](crate::TraceIdConfigBuilder::correlation_header). Besides being
+/// available through this extractor, they're recorded together in the `correlation` field of the
+/// `http-request` span, as `name=value` pairs. The trace ID stays the primary ID the span is keyed
+/// on.
+///
+/// ```rust
+/// use trace_id_layer::{CorrelationContext, TraceIdConfig};
+///
+/// let config = TraceIdConfig::builder()
+///     .correlation_header("tenant_id", "x-tenant-id")
+///     .correlation_header("session_id", "x-session-id")
+///     .build().unwrap();
+///
+/// async fn my_handler(context: CorrelationContext) -> String {
+///     let tenant_id = context.get("tenant_id").unwrap_or("<none>");
+///     format!("{} for tenant {tenant_id}", context.trace_id())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CorrelationContext {
+    trace_id: TraceId,
+    ids: Vec<(Cow<'static, str>, String)>,
+}
+
+impl CorrelationContext {
+    /// Reads the `headers` declared in `mappings`, skipping missing and non-ASCII values.
+    pub(crate) fn new(
+        trace_id: TraceId,
+        headers: &HeaderMap,
+        mappings: &[(Cow<'static, str>, HeaderName)],
+    ) -> Self {
+        let ids = mappings
+            .iter()
+            .filter_map(|(name, header_name)| {
+                let value = headers.get(header_name)?.to_str().ok()?;
+                Some((name.clone(), value.to_owned()))
+            })
+            .collect();
+        Self { trace_id, ids }
+    }
+
+    /// The trace ID of the request.
+    pub fn trace_id(&self) -> &TraceId {
+        &self.trace_id
+    }
+
+    /// The correlation ID declared as `name`, if the request carried it.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.ids
+            .iter()
+            .find(|(id_name, _)| id_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The correlation IDs the request carried, in declaration order, without the trace ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.ids
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_str()))
+    }
+
+    /// The `correlation` span field, if the request carried any correlation ID.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span_value(&self) -> Option<String> {
+        let ids = self
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>();
+        (!ids.is_empty()).then(|| ids.join(" "))
+    }
+}
+
+impl<S> FromRequestParts<S> for CorrelationContext
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "CorrelationContext extension missing. Did you apply add_trace_id_middleware?";
+        parts
+            .extensions
+            .get::<CorrelationContext>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
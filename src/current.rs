@@ -0,0 +1,128 @@
+use std::panic;
+
+use tokio::{task::JoinHandle, task_local};
+
+use crate::TraceId;
+
+task_local! {
+    static CURRENT_TRACE_ID: TraceId;
+}
+
+/// The trace ID of the request being handled by the current task, if any.
+///
+/// Set by the middleware for the whole request, so it's available deep in code that has no access
+/// to the request or the `http-request` span, without threading the [`TraceId`] through. Like any
+/// task-local, it isn't inherited by tasks started with `tokio::spawn`, nor visible on other
+/// threads (`spawn_blocking`).
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{add_trace_id_middleware, current_trace_id};
+///
+/// fn deep_in_the_stack() -> String {
+///     current_trace_id().map(|trace_id| trace_id.to_string()).unwrap_or_default()
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(|| async { deep_in_the_stack() })));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+///
+/// assert!(current_trace_id().is_none());
+/// # }
+/// ```
+pub fn current_trace_id() -> Option<TraceId> {
+    CURRENT_TRACE_ID.try_with(TraceId::clone).ok()
+}
+
+/// Runs `future` with `trace_id` as the [`current_trace_id`].
+pub(crate) async fn scope<F: Future>(trace_id: TraceId, future: F) -> F::Output {
+    CURRENT_TRACE_ID.scope(trace_id, future).await
+}
+
+/// Runs `f` on tokio's blocking thread pool, with the trace ID and span of the caller.
+///
+/// A plain `tokio::task::spawn_blocking` loses both, so CPU-bound work (hashing, compression)
+/// would log without the trace ID. Here, [`current_trace_id`] returns the caller's trace ID inside
+/// `f`, and with the `tracing` feature the current span (usually `http-request`) is entered for
+/// the whole time `f` runs. Unlike `spawn_traced` (`tracing` feature), no child span is created:
+/// a blocking closure runs in one go, so there's no polling to time separately.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{add_trace_id_middleware, current_trace_id, spawn_blocking_traced};
+///
+/// async fn handler() -> String {
+///     spawn_blocking_traced(|| {
+///         tracing::info!("hashing password");
+///         current_trace_id().unwrap().to_string()
+///     })
+///     .await
+///     .unwrap()
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+/// # }
+/// ```
+pub fn spawn_blocking_traced<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let trace_id = current_trace_id();
+    #[cfg(feature = "tracing")]
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        #[cfg(feature = "tracing")]
+        let _entered = span.entered();
+        match trace_id {
+            Some(trace_id) => CURRENT_TRACE_ID.sync_scope(trace_id, f),
+            None => f(),
+        }
+    })
+}
+
+/// Installs a global panic hook printing the [`current_trace_id`] to stderr, before running the
+/// previously installed hook.
+///
+/// A blunt safety net answering "which request caused the crash": call it once at startup, after
+/// any other panic hook is installed. It relies on the task-local set by the middleware, so panics
+/// outside of a request, or in tasks spawned from one, print nothing extra.
+///
+/// ```rust
+/// trace_id_layer::install_panic_hook();
+///
+/// // thread 'tokio-runtime-worker' panicked with trace_id '0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e'
+/// // thread 'tokio-runtime-worker' panicked at src/main.rs:12:5:
+/// // ...
+/// ```
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Some(trace_id) = current_trace_id() {
+            let thread = std::thread::current();
+            let name = thread.name().unwrap_or("<unnamed>");
+            eprintln!("thread '{name}' panicked with trace_id '{trace_id}'");
+        }
+        previous(info);
+    }));
+}
@@ -0,0 +1,164 @@
+use std::{borrow::Cow, fmt::Display};
+
+use axum::{
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use http::{StatusCode, header::CONTENT_TYPE};
+
+use crate::TraceId;
+
+/// A fallback handler answering unmatched routes with `404 Not Found` and a JSON body carrying the
+/// trace ID, so clients hitting a wrong route still have an ID to report.
+///
+/// Mount it with `Router::fallback` before adding the middleware, so the middleware wraps it:
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::{Request, StatusCode};
+/// use tower::ServiceExt;
+/// use trace_id_layer::add_trace_id_middleware;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = Router::new()
+///     .route("/", get(|| async {}))
+///     .fallback(trace_id_layer::fallback)
+///     .method_not_allowed_fallback(trace_id_layer::method_not_allowed);
+/// let router = add_trace_id_middleware(router);
+///
+/// let request = Request::get("/missing")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::NOT_FOUND);
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(body, r#"{"error":"Not Found","trace_id":"0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"}"#);
+/// # }
+/// ```
+///
+/// Without the middleware, `trace_id` is `null`.
+pub async fn fallback(request: Request) -> Response {
+    error_response(StatusCode::NOT_FOUND, &request)
+}
+
+/// Like [`fallback`], but for routes matched with an unsupported method, answered with
+/// `405 Method Not Allowed`. Mount it with `Router::method_not_allowed_fallback`.
+pub async fn method_not_allowed(request: Request) -> Response {
+    error_response(StatusCode::METHOD_NOT_ALLOWED, &request)
+}
+
+fn error_response(status: StatusCode, request: &Request) -> Response {
+    json_response(status, None, request.extensions().get::<TraceId>())
+}
+
+/// A rejection of a request whose trace ID was read fine, but breaks a rule of the handler, e.g.
+/// it's from the wrong namespace.
+///
+/// Responds with `400 Bad Request` by default, and a JSON body citing the offending trace ID, in
+/// the same shape as [`fallback`]'s, with the reason added:
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::{Request, StatusCode};
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceId, TraceIdRejection, add_trace_id_middleware};
+///
+/// async fn handler(trace_id: TraceId) -> Result<String, TraceIdRejection> {
+///     if trace_id.uuid().is_none_or(|uuid| uuid.get_version_num() != 7) {
+///         return Err(TraceIdRejection::new(trace_id, "trace ID must be a UUIDv7"));
+///     }
+///     Ok(format!("Hello, {trace_id}"))
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "67e55044-10b1-426f-9247-bb680e5fe0c8")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(
+///     body,
+///     r#"{"error":"Bad Request","reason":"trace ID must be a UUIDv7","trace_id":"67e55044-10b1-426f-9247-bb680e5fe0c8"}"#,
+/// );
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceIdRejection {
+    trace_id: TraceId,
+    reason: Cow<'static, str>,
+    status: StatusCode,
+}
+
+impl TraceIdRejection {
+    /// Rejects `trace_id` for `reason`, with `400 Bad Request`.
+    pub fn new(trace_id: TraceId, reason: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            trace_id,
+            reason: reason.into(),
+            status: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Responds with `status` instead, e.g. `403 Forbidden`.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// The rejected trace ID.
+    pub fn trace_id(&self) -> &TraceId {
+        &self.trace_id
+    }
+
+    /// Why the trace ID was rejected.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl Display for TraceIdRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rejected trace ID '{}': {}", self.trace_id, self.reason)
+    }
+}
+
+impl std::error::Error for TraceIdRejection {}
+
+impl IntoResponse for TraceIdRejection {
+    fn into_response(self) -> Response {
+        json_response(self.status, Some(&self.reason), Some(&self.trace_id))
+    }
+}
+
+/// A JSON error response for `status`, with an optional reason, and the trace ID or `null`.
+fn json_response(status: StatusCode, reason: Option<&str>, trace_id: Option<&TraceId>) -> Response {
+    let error = status.canonical_reason().unwrap_or_default();
+    let reason = reason
+        .map(|reason| format!(r#","reason":"{}""#, json_escape(reason)))
+        .unwrap_or_default();
+    let trace_id = match trace_id {
+        Some(trace_id) => format!("\"{}\"", json_escape(&trace_id.to_string())),
+        None => "null".to_owned(),
+    };
+    let body = format!(r#"{{"error":"{error}"{reason},"trace_id":{trace_id}}}"#);
+    (status, [(CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Escapes `value` for a JSON string, since free-form trace IDs can hold any character.
+pub(crate) fn json_escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut escaped, char| {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            char if char.is_control() => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+        escaped
+    })
+}
@@ -0,0 +1,56 @@
+//! [`async-graphql`](https://docs.rs/async-graphql) integration.
+//!
+//! A single HTTP request can run many resolvers. Pass the request's [`TraceId`] into the GraphQL
+//! context with [`with_trace_id`], and read it back in any resolver with [`trace_id`]. The schema
+//! executes within the handler, so its logs are also in the `http-request` span.
+//!
+//! ```rust
+//! use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema};
+//! use axum::{Router, body::Body, routing::get};
+//! use http::Request;
+//! use tower::ServiceExt;
+//! use trace_id_layer::{TraceId, add_trace_id_middleware, graphql};
+//!
+//! struct Query;
+//!
+//! #[Object]
+//! impl Query {
+//!     async fn trace_id(&self, ctx: &Context<'_>) -> String {
+//!         let trace_id = graphql::trace_id(ctx).map(TraceId::to_string);
+//!         tracing::info!(?trace_id, "resolving");
+//!         trace_id.unwrap_or_default()
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+//! let handler = move |trace_id: TraceId| async move {
+//!     let request = graphql::with_trace_id("{ traceId }".into(), trace_id);
+//!     schema.execute(request).await.data.to_string()
+//! };
+//! let router = add_trace_id_middleware(Router::new().route("/graphql", get(handler)));
+//!
+//! let request = Request::get("/graphql")
+//!     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let response = router.oneshot(request).await.unwrap();
+//! let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+//! assert_eq!(body, r#"{traceId: "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"}"#);
+//! # }
+//! ```
+
+use async_graphql::{Context, Request};
+
+use crate::TraceId;
+
+/// Adds `trace_id` to the data of the GraphQL `request`, for [`trace_id`] to read.
+pub fn with_trace_id(request: Request, trace_id: TraceId) -> Request {
+    request.data(trace_id)
+}
+
+/// The trace ID added with [`with_trace_id`], if any.
+pub fn trace_id<'a>(ctx: &Context<'a>) -> Option<&'a TraceId> {
+    ctx.data_opt::<TraceId>()
+}
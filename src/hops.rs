@@ -0,0 +1,69 @@
+use std::{convert::Infallible, fmt::Display};
+
+use axum::extract::OptionalFromRequestParts;
+use http::{HeaderMap, HeaderName, HeaderValue, request::Parts};
+
+/// The header carrying the number of services a request went through.
+pub const TRACE_HOPS_HEADER: HeaderName = HeaderName::from_static("x-trace-hops");
+
+/// The default of [`TraceIdConfigBuilder::max_hops`](crate::TraceIdConfigBuilder::max_hops).
+pub const DEFAULT_MAX_HOPS: u32 = 32;
+
+/// The number of services a request went through, this one included, read from the
+/// [`TRACE_HOPS_HEADER`] and incremented.
+///
+/// Enable it with [`hop_count`](crate::TraceIdConfigBuilder::hop_count); it's then recorded as
+/// the `hops` span field, sent downstream by [`RequestContext::inject`](crate::RequestContext),
+/// and available as an extractor:
+///
+/// ```rust
+/// use trace_id_layer::HopCount;
+///
+/// async fn my_handler(hops: Option<HopCount>) -> String {
+///     hops.map(|hops| format!("hop {hops}")).unwrap_or_default()
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HopCount(u32);
+
+impl HopCount {
+    /// Reads the inbound hop count, a missing or invalid header counting as 0, and adds this hop.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let inbound = headers
+            .get(TRACE_HOPS_HEADER)
+            .and_then(|value| value.to_str().ok()?.trim().parse::<u32>().ok())
+            .unwrap_or_default();
+        Self(inbound.saturating_add(1))
+    }
+
+    /// The hop count, 1 for a request coming straight from a client.
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    /// Sets the [`TRACE_HOPS_HEADER`] in `headers` for an outbound request, so the next service
+    /// counts on from this one.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        headers.insert(TRACE_HOPS_HEADER, HeaderValue::from(self.0));
+    }
+}
+
+impl Display for HopCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for HopCount
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<HopCount>().copied())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{convert::Infallible, fmt::Display, ops::Deref};
+
+use axum::extract::OptionalFromRequestParts;
+use http::{HeaderMap, HeaderName, request::Parts};
+
+/// The idempotency key the client sent with the request, telling retries apart from new requests.
+///
+/// Enable it with [`idempotency_header`](crate::TraceIdConfigBuilder::idempotency_header); it's
+/// then recorded as the `idempotency_key` span field and available as an extractor. Requests
+/// without the header have no key, so extract it as an `Option`:
+///
+/// ```rust
+/// use trace_id_layer::{IdempotencyKey, TraceId};
+///
+/// async fn my_handler(trace_id: TraceId, key: Option<IdempotencyKey>) -> String {
+///     match key {
+///         Some(key) => format!("{trace_id} (idempotency key {key})"),
+///         None => trace_id.to_string(),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    /// Reads the key from the `header_name` header, if it's present and valid UTF-8.
+    pub(crate) fn from_headers(headers: &HeaderMap, header_name: &HeaderName) -> Option<Self> {
+        let value = headers.get(header_name)?.to_str().ok()?;
+        Some(Self(value.to_owned()))
+    }
+}
+
+impl Deref for IdempotencyKey {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for IdempotencyKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for IdempotencyKey
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<IdempotencyKey>().cloned())
+    }
+}
@@ -1,136 +1,881 @@
-use std::{fmt::Display, ops::Deref, time::Duration};
+use std::sync::{Arc, Once};
 
 use axum::{
-    extract::FromRequestParts,
+    extract::{FromRef, State},
     middleware::{self, Next},
-    response::Response,
+    response::{IntoResponse, Response},
+};
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Request, StatusCode,
+    header::{ACCESS_CONTROL_EXPOSE_HEADERS, ORIGIN, VARY},
 };
-use http::{HeaderMap, Request, StatusCode, request::Parts};
 use tower::ServiceBuilder;
-use tower_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
-use tracing::{Span, error, info};
-use uuid::Uuid;
 
-const TRACE_ID_HEADER: &str = "x-trace-id";
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing as __tracing;
 
-/// The trace ID extracted or generated for this request.
-///
-/// Use this as an extractor in your handlers to access the trace ID:
-///
-/// ```rust
-/// use trace_id_layer::TraceId;
-///
-/// async fn my_handler(trace_id: TraceId) -> String {
-///     format!("Request trace ID: {}", trace_id)
-/// }
-/// ```
-#[derive(Debug, Clone)]
-pub struct TraceId(Uuid);
+mod audit;
+#[cfg(feature = "baggage")]
+mod baggage;
+#[cfg(feature = "tracing")]
+mod body;
+mod client;
+mod clock;
+mod config;
+mod context;
+pub mod core;
+mod correlation;
+mod current;
+mod fallback;
+#[cfg(feature = "async-graphql")]
+pub mod graphql;
+mod hops;
+mod idempotency;
+mod log;
+mod marker;
+#[cfg(feature = "log-mdc")]
+mod mdc;
+#[cfg(feature = "prometheus-exemplars")]
+pub mod metrics;
+#[cfg(feature = "tracing")]
+mod preset;
+mod recent;
+pub mod require;
+#[cfg(feature = "slog")]
+mod scoped_logger;
+mod seq;
+mod short_id;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "tracing")]
+mod span;
+#[cfg(feature = "subscriber")]
+pub mod subscriber;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
+#[cfg(feature = "traceparent")]
+mod trace_context;
+mod trace_id;
+
+pub use audit::AuditRecord;
+#[cfg(feature = "baggage")]
+pub use baggage::{BAGGAGE_HEADER, Baggage, MAX_BAGGAGE_BYTES, MAX_BAGGAGE_ENTRIES};
+#[cfg(feature = "tracing")]
+use body::{EosLog, TrailerTraceId};
+pub use client::{SetTraceId, SetTraceIdLayer};
+pub use clock::CoarseClock;
+#[cfg(feature = "tracing")]
+pub use config::AccessLogField;
+pub use config::{
+    ActiveConfig, ConfigError, FormattedTraceId, IdFormat, InboundTrust, InvalidHeader, SpanField,
+    TraceIdConfig, TraceIdConfigBuilder,
+};
+pub use context::RequestContext;
+pub use core::{TRACE_ID_HEADER, TraceIdSource};
+pub use correlation::CorrelationContext;
+pub use current::{current_trace_id, install_panic_hook, spawn_blocking_traced};
+pub use fallback::{TraceIdRejection, fallback, method_not_allowed};
+pub use hops::{DEFAULT_MAX_HOPS, HopCount, TRACE_HOPS_HEADER};
+pub use idempotency::IdempotencyKey;
+#[cfg(feature = "tracing")]
+pub use preset::{FullObservability, full_observability};
+pub use recent::recent_requests_handler;
+pub use require::RequireTraceIdLayer;
+pub use seq::RequestSeq;
+#[cfg(feature = "signing")]
+pub use signing::TRACE_ID_SIG_HEADER;
+#[cfg(feature = "tracing")]
+pub use span::{record_middleware_latency, spawn_traced};
+#[cfg(feature = "traceparent")]
+pub use trace_context::{TRACEPARENT_HEADER, TRACESTATE_HEADER, TraceContext};
+pub use trace_id::{MissingTraceId, ParentId, ParseTraceIdError, RawTraceHeader, TraceId};
+
+use crate::log::{debug, error, warn};
 
-impl Deref for TraceId {
-    type Target = Uuid;
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+/// Marks requests that already went through [`inject_trace_id`]
+#[derive(Clone, Copy)]
+struct MiddlewareApplied;
+
+/// Middleware to inject trace_id into request extensions
+async fn inject_trace_id(
+    State(config): State<Arc<TraceIdConfig>>,
+    mut request: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    // Keep the outer application's trace ID if the middleware was applied twice
+    if request.extensions().get::<MiddlewareApplied>().is_some() {
+        static WARN_ONCE: Once = Once::new();
+        WARN_ONCE.call_once(|| {
+            warn!("Trace ID middleware applied more than once. Check your layer stack for duplicate add_trace_id_middleware calls")
+        });
+        let outer = request.extensions().get::<ActiveConfig>();
+        if outer.is_some_and(|outer| outer.conflicts_with(&config)) {
+            static WARN_CONFLICT_ONCE: Once = Once::new();
+            WARN_CONFLICT_ONCE.call_once(|| {
+                warn!("Trace ID middleware applied with conflicting configs (header name, format or span field). The outer one applies")
+            });
+        }
+        return next.run(request).await;
     }
-}
 
-impl Display for TraceId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+    if config.skip_methods.contains(request.method()) {
+        return next.run(request).await;
     }
-}
 
-impl TraceId {
-    pub fn uuid(&self) -> Uuid {
-        self.0
+    let hops = config
+        .max_hops
+        .is_some()
+        .then(|| HopCount::from_headers(request.headers()));
+    if let (Some(hops), Some(max_hops)) = (hops, config.max_hops)
+        && hops.get() > max_hops
+    {
+        if !config.dry_run {
+            warn!(hops = %hops, max_hops, "Rejecting request over the max hop count");
+            let message = format!("Too many hops: {hops} > {max_hops}");
+            return (StatusCode::LOOP_DETECTED, message).into_response();
+        }
+        warn!(hops = %hops, max_hops, "Dry run: would reject request over the max hop count");
     }
-}
 
-impl<S> FromRequestParts<S> for TraceId
-where
-    S: Send + Sync,
-{
-    type Rejection = (StatusCode, &'static str);
+    let (mut parts, body) = request.into_parts();
+    let header_name = config.header_name_for(&parts);
+    let stripped = match config.inbound_trust {
+        InboundTrust::Untrusted => parts.headers.remove(&*header_name),
+        _ => None,
+    };
+    if stripped.is_some() {
+        debug!("Stripped untrusted inbound trace_id");
+    }
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        const ERR_MSG: &str = "TraceId extension missing. Did you apply add_trace_id_middleware?";
-        parts
-            .extensions
-            .get::<TraceId>()
-            .cloned()
-            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
-            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    // The configured source takes precedence over the header
+    let mut custom = config
+        .source
+        .as_ref()
+        .and_then(|source| source.call(&parts));
+    if let (None, Some(source)) = (&custom, &config.async_source) {
+        custom = source.call(&parts).await;
     }
-}
 
-/// Middleware to inject trace_id into request extensions
-async fn inject_trace_id(mut request: Request<axum::body::Body>, next: Next) -> Response {
     // Extract or generate trace-id
-    let trace_id = if let Some(trace_id) =
-        request.headers().get(TRACE_ID_HEADER).and_then(|v| {
-            v.to_str()
-                .inspect_err(|e| error!("Unable to convert trace-id header to string: '{e:?}'"))
-                .ok()
-                .and_then(|trace_id| Uuid::parse_str(trace_id).inspect_err(|e| error!("Unable to parce trace-id header to Uuid. Received: '{trace_id}'. Error: '{e:?}'")).ok())
-        }) {
-        trace_id
+    #[cfg(feature = "tracing")]
+    let mut fallback_header = None;
+    let (mut trace_id, source) = match custom {
+        Some(trace_id) => (trace_id, TraceIdSource::Custom),
+        None => match config.extract_with_fallback(&parts.headers, &header_name) {
+            // Fallback values are checked like the header: only the edge issues trace IDs
+            #[cfg(feature = "signing")]
+            Ok(Some((trace_id, _)))
+                if config
+                    .signing_key
+                    .as_ref()
+                    .is_some_and(|key| !key.verify(&trace_id, &parts.headers)) =>
+            {
+                warn!("Ignoring inbound trace_id with a missing or invalid signature");
+                (config.generate(&parts), TraceIdSource::Generated)
+            }
+            Ok(Some((trace_id, None))) => (trace_id, TraceIdSource::Header),
+            Ok(Some((trace_id, Some(header_name)))) => {
+                debug!(header = %header_name, "Adopted trace_id from fallback header");
+                #[cfg(feature = "tracing")]
+                {
+                    fallback_header = Some(header_name.clone());
+                }
+                (trace_id, TraceIdSource::Fallback)
+            }
+            Ok(None) if config.requires_inbound(&parts.method) => {
+                if !config.dry_run {
+                    warn!("Rejecting request without trace_id");
+                    return config.missing_response(&header_name).into_response();
+                }
+                warn!("Dry run: would reject request without trace_id");
+                (config.generate(&parts), TraceIdSource::Generated)
+            }
+            Ok(None) => (config.generate(&parts), TraceIdSource::Generated),
+            Err(e) => {
+                if let Some(suppressed) = config.invalid_header_log.admit() {
+                    if suppressed > 0 {
+                        warn!(suppressed, "Suppressed invalid inbound trace_id logs");
+                    }
+                    // Only dumped for the log line
+                    #[cfg(feature = "tracing")]
+                    let raw = config
+                        .log_invalid_bytes
+                        .then(|| hex(parts.headers[&*header_name].as_bytes()));
+                    #[cfg(not(feature = "tracing"))]
+                    let raw = ();
+                    error!(error = %e, raw, "Invalid inbound trace_id");
+                }
+                match config.invalid_header {
+                    InvalidHeader::Reject if !config.dry_run => {
+                        return config.invalid_response(&header_name).into_response();
+                    }
+                    InvalidHeader::Reject => {
+                        warn!("Dry run: would reject request with invalid trace_id");
+                        (config.generate(&parts), TraceIdSource::Generated)
+                    }
+                    InvalidHeader::Regenerate => {
+                        (config.generate(&parts), TraceIdSource::Generated)
+                    }
+                }
+            }
+        },
+    };
+
+    // In chain mode, the inbound trace ID becomes this hop's parent
+    let parent_id = if config.chain && source != TraceIdSource::Generated {
+        let hop_id = TraceId::from(config.generate_v7());
+        Some(ParentId(std::mem::replace(&mut trace_id, hop_id)))
     } else {
-        Uuid::now_v7()
+        None
     };
+    request = Request::from_parts(parts, body);
+    if config.dry_run {
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            trace_id = %trace_id,
+            parent_id = parent_id.as_ref().map(tracing::field::display),
+            source = ?source,
+            "Dry run: would assign trace_id"
+        );
+        if let Some(value) = stripped {
+            request
+                .headers_mut()
+                .insert(header_name.into_owned(), value);
+        }
+        return next.run(request).await;
+    }
+
+    let raw_header = RawTraceHeader(request.headers().get(&*header_name).cloned());
+    // Only a trace ID adopted from the header can be echoed as it was sent
+    let verbatim = (config.echo_verbatim && source == TraceIdSource::Header && parent_id.is_none())
+        .then(|| raw_header.0.clone())
+        .flatten();
 
     // Store in request extensions for handler access
-    request.extensions_mut().insert(TraceId(trace_id));
+    let correlation = CorrelationContext::new(
+        trace_id.clone(),
+        request.headers(),
+        &config.correlation_headers,
+    );
+    let idempotency_key = config
+        .idempotency_header
+        .as_ref()
+        .and_then(|header_name| IdempotencyKey::from_headers(request.headers(), header_name));
+    #[cfg(feature = "baggage")]
+    let baggage = config
+        .baggage
+        .then(|| Baggage::from_headers(request.headers()));
+    #[cfg(feature = "traceparent")]
+    let trace_context = config
+        .trace_context
+        .then(|| TraceContext::from_headers(request.headers()))
+        .flatten();
+    let context = RequestContext::new(
+        parent_id.clone(),
+        correlation.clone(),
+        #[cfg(feature = "baggage")]
+        baggage.clone(),
+        #[cfg(feature = "traceparent")]
+        trace_context.clone(),
+        hops,
+        config.clone(),
+    );
+    let extensions = request.extensions_mut();
+    extensions.insert(trace_id.clone());
+    extensions.insert(correlation);
+    extensions.insert(context);
+    extensions.insert(source);
+    extensions.insert(raw_header);
+    if config.request_seq {
+        extensions.insert(RequestSeq::next());
+    }
+    if let Some(idempotency_key) = idempotency_key {
+        extensions.insert(idempotency_key);
+    }
+    if let Some(hops) = hops {
+        extensions.insert(hops);
+    }
+    #[cfg(feature = "baggage")]
+    if let Some(baggage) = baggage {
+        extensions.insert(baggage);
+    }
+    #[cfg(feature = "traceparent")]
+    if let Some(trace_context) = trace_context {
+        extensions.insert(trace_context);
+    }
+    if let Some(parent_id) = parent_id {
+        extensions.insert(parent_id);
+    }
+    #[cfg(feature = "tracing")]
+    if let Some(header_name) = fallback_header {
+        extensions.insert(span::FallbackHeader(header_name));
+    }
+    extensions.insert(MiddlewareApplied);
+    extensions.insert(ActiveConfig(config.clone()));
+    if let Some(file) = &config.last_request_file {
+        file.record(&trace_id);
+    }
+
+    #[cfg(feature = "tracing")]
+    if config.trailer_trace_id {
+        let trace_id = trace_id.clone();
+        let header_name = header_name.clone().into_owned();
+        request = request
+            .map(|body| axum::body::Body::new(TrailerTraceId::new(body, trace_id, header_name)));
+    }
+
+    let origin = config
+        .echo_origins
+        .is_some()
+        .then(|| request.headers().get(ORIGIN).cloned())
+        .flatten();
+    let start = std::time::Instant::now();
+    let records = config.on_complete.is_some() || config.recent_requests.is_some();
+    let request_line = (records || config.logs_access())
+        .then(|| (request.method().clone(), request.uri().path().to_owned()));
+    #[cfg(feature = "tracing")]
+    request
+        .extensions_mut()
+        .insert(span::Handoff(std::time::Instant::now()));
+    #[cfg(feature = "tracing")]
+    let guard = span::CancellationGuard::new(trace_id.clone());
+    let run = async {
+        let run = next.run(request);
+        #[cfg(feature = "slog")]
+        let run = scoped_logger::WithSlogScope::new(
+            run,
+            config.slog_logger.as_ref().map(|logger| {
+                logger.new(slog::o!(config.span_field.as_str() => trace_id.to_string()))
+            }),
+        );
+        #[cfg(feature = "log-mdc")]
+        if config.log_mdc {
+            let trace_id = trace_id.to_string();
+            return mdc::WithMdc::new(run, config.span_field.as_str(), trace_id).await;
+        }
+        run.await
+    };
+    let mut response = current::scope(trace_id.clone(), run).await;
+    #[cfg(feature = "tracing")]
+    guard.complete();
+    #[cfg(feature = "prometheus-exemplars")]
+    if let Some(metrics) = &config.metrics {
+        metrics.observe(response.status(), start.elapsed(), &trace_id);
+    }
+    if let Some((method, path)) = request_line {
+        #[cfg(feature = "tracing")]
+        if let Some(level) = config.access_log {
+            let level = config.request_log_level(&method, level);
+            let request_line = (&method, path.as_str());
+            span::log_access(
+                level,
+                &config,
+                &trace_id,
+                request_line,
+                &response,
+                start.elapsed(),
+            );
+        }
+        if records {
+            let record = AuditRecord {
+                trace_id: trace_id.clone(),
+                method,
+                path,
+                status: response.status(),
+                latency: start.elapsed(),
+            };
+            if let Some(recent) = &config.recent_requests {
+                recent.record(record.clone());
+            }
+            if let Some(on_complete) = &config.on_complete {
+                on_complete.call(record);
+            }
+        }
+    }
 
-    next.run(request).await
+    #[cfg(feature = "tracing")]
+    if !config.logs_access() && !config.covers_body() {
+        let trace_id = trace_id.clone();
+        let level = config.eos_level;
+        response = response.map(|body| axum::body::Body::new(EosLog::new(body, trace_id, level)));
+    }
+
+    if config.sets_response_headers() {
+        let echo = (config.echoes(response.status()) && config.echoes_to(origin.as_ref()))
+            .then_some(&*header_name);
+        set_response_headers(response.headers_mut(), &trace_id, echo, verbatim, &config);
+        // Let scripts of an allowed origin read the header
+        if let (Some(header_name), Some(_)) = (echo, &origin) {
+            response.headers_mut().append(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from(header_name.clone()),
+            );
+        }
+        // The response depends on the origin, so caches must not share it across origins
+        if config.echo_origins.is_some() {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("origin"));
+        }
+    }
+
+    response
+}
+
+/// Hex-encodes `bytes`, in lowercase
+#[cfg(any(feature = "tracing", feature = "signing"))]
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
 }
 
+/// Adds the configured trace ID response headers
+///
+/// The trace ID is only echoed to the `echo` header if set, with `verbatim` instead of the
+/// formatted trace ID when set.
+fn set_response_headers(
+    headers: &mut HeaderMap,
+    trace_id: &TraceId,
+    echo: Option<&HeaderName>,
+    verbatim: Option<HeaderValue>,
+    config: &TraceIdConfig,
+) {
+    if let Some(header_name) = echo {
+        match verbatim.map_or_else(|| HeaderValue::try_from(config.render(trace_id)), Ok) {
+            Ok(value) => {
+                headers.insert(header_name.clone(), value);
+            }
+            Err(e) => error!(
+                trace_id = %trace_id,
+                error = ?e,
+                "Unable to convert trace_id to a header value"
+            ),
+        }
+    }
+
+    if config.server_timing {
+        match HeaderValue::try_from(format!("trace;desc=\"{}\"", config.render(trace_id))) {
+            Ok(value) => {
+                headers.append(SERVER_TIMING, value);
+            }
+            Err(e) => error!(
+                trace_id = %trace_id,
+                error = ?e,
+                "Unable to convert trace_id to a header value"
+            ),
+        }
+    }
+
+    #[cfg(feature = "signing")]
+    if let Some(key) = &config.signing_key {
+        match HeaderValue::try_from(key.sign(trace_id)) {
+            Ok(value) => {
+                headers.insert(TRACE_ID_SIG_HEADER, value);
+            }
+            Err(e) => {
+                error!(
+                    trace_id = %trace_id,
+                    error = ?e,
+                    "Unable to convert the trace_id signature to a header value"
+                )
+            }
+        }
+    }
+}
+
+/// Adds trace ID injection and a `tower_http` `TraceLayer` to `router`.
+///
+/// Without the `tracing` feature, only the trace ID injection is added.
+///
+/// Every request is wrapped in an `http-request` span carrying a `trace_id` field (see
+/// [`TraceIdConfigBuilder::span_field`] to rename it), which is recorded when the span is created,
+/// and an `outcome` field (`success`, `client_error` or `server_error`) recorded from the final
+/// response status. Server errors also record `error.kind` (`status` for a 5xx response, `error`
+/// for a failed service) and `error.message`. In [chain mode](TraceIdConfigBuilder::chain), the
+/// inbound ID is recorded as `parent_id`. [Static attributes](TraceIdConfigBuilder::span_attribute)
+/// are recorded as `attributes`, [correlation IDs](CorrelationContext) as `correlation`,
+/// [idempotency keys](IdempotencyKey) as `idempotency_key`, the [hop count](HopCount) as `hops`,
+/// the age of the inbound ID (`record_inbound_age`) as `inbound_age_ms`, the
+/// [fallback header](TraceIdConfigBuilder::fallback_headers) a trace ID was read from as
+/// `trace_id.header`, response attributes (`response_field`) as `response_attributes`, and the
+/// streamed body (`span_covers_body`) as `body.bytes` and `body.duration_ms`. The span:
+///
+/// - opens before the inner service is called, and is entered on every poll of the handler future,
+/// - stays alive inside the response body, so it only closes once the body has been fully streamed
+///   (or dropped by the client). For streaming responses (SSE, large downloads) that is well after
+///   the `on_response` log line.
+///
+/// To get span open/close events with the trace ID, enable them on the subscriber, e.g.
+/// `tracing_subscriber::fmt().with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)`. The close event's
+/// `time.busy`/`time.idle` then cover the full request, body included.
+///
+/// The middleware's own log events use structured fields rather than interpolated messages, with
+/// the trace ID as their first field, `trace_id`, so logfmt and JSON formatters can index them:
+///
+/// ```text
+/// INFO http-request{trace_id=0192f0c4-...}: trace_id_layer::span: Received request with trace_id trace_id=0192f0c4-... method=GET uri=/
+/// ```
+///
+/// # Handler logs
+///
+/// The `TraceLayer` enters the span on every poll of the handler future, so everything the
+/// handler logs, including from nested `async fn`s it awaits, carries the trace ID. Work moved off
+/// the handler future, e.g. with `tokio::spawn`, doesn't: spawn it with `spawn_traced` or
+/// instrument it with `tracing::Instrument::in_current_span`.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::add_trace_id_middleware;
+///
+/// async fn load_user() {
+///     tracing::info!("loading user");
+/// }
+///
+/// async fn handler() {
+///     tokio::task::yield_now().await;
+///     load_user().await;
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.oneshot(request).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}: rust_out: loading user
+/// # }
+/// ```
+///
+/// # Profiling
+///
+/// The span is always named `http-request`, and is entered only while the request is being
+/// polled, so profilers built on span enter/exit, such as
+/// [`tracing-flame`](https://docs.rs/tracing-flame), attribute busy time to it and to the spans
+/// nested in it, and not time spent waiting. Register the flame layer on the same subscriber:
+///
+/// ```rust,ignore
+/// let (flame_layer, _guard) = tracing_flame::FlameLayer::with_file("./tracing.folded")?;
+/// tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer())
+///     .with(flame_layer.with_threads_collapsed(true))
+///     .init();
+/// ```
+///
+/// Folded stacks are keyed on span names, not fields, so the flamegraph aggregates all requests
+/// rather than splitting them per trace ID. To profile individual requests, use a profiler
+/// recording span fields, such as [`tracing-chrome`](https://docs.rs/tracing-chrome), whose trace
+/// viewer shows each `http-request` span with its `trace_id`.
+///
+/// # Timeouts and cancellation
+///
+/// Where a timeout layer sits relative to this middleware decides how a timeout is logged:
+///
+/// - applied to the router *before* this middleware (i.e. inside it), the timeout produces a
+///   response or error that goes through the `TraceLayer` as usual, so `on_response`/`on_failure`
+///   log it within the `http-request` span;
+/// - applied *after* this middleware (i.e. outside it), the timeout drops the request future and
+///   the span with it. A warning naming the trace ID is logged when that happens, which also covers
+///   clients that disconnect before a response is produced.
+///
+/// # Layer order
+///
+/// Layers added to the router before this middleware run inside it, and see requests after it and
+/// responses before it. The `http-request` span is entered while they run, so their logs carry the
+/// trace ID, and spans they create (e.g. another `tower_http` layer's) are its children. Layers
+/// added after this middleware run outside the span, before the trace ID exists: add layers whose
+/// logs should be correlated first.
+///
+/// ```rust
+/// use axum::{Router, body::Body, middleware::{self, Next}, extract::Request, routing::get};
+/// use tower::ServiceExt;
+/// use trace_id_layer::add_trace_id_middleware;
+///
+/// async fn auth(request: Request, next: Next) -> axum::response::Response {
+///     tracing::info!("checking credentials");
+///     next.run(request).await
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // `auth` runs inside the trace ID middleware
+/// let router = Router::new().route("/", get(|| async {})).layer(middleware::from_fn(auth));
+/// let router = add_trace_id_middleware(router);
+///
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.oneshot(request).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}: rust_out: checking credentials
+/// # }
+/// ```
+///
+/// # Auth rejections
+///
+/// Response headers set by the middleware, such as the [echoed](TraceIdConfigBuilder::echo_header)
+/// trace ID, are set on every response that goes through it, including `401`/`403` rejections
+/// produced by inner layers before any handler runs. Add the auth layer *before* this middleware,
+/// so clients reporting an auth failure have a trace ID to quote. An auth layer added after it
+/// rejects requests before the trace ID exists.
+///
+/// ```rust
+/// use axum::{Router, body::Body, extract::Request, middleware::{self, Next}, response::{IntoResponse, Response}, routing::get};
+/// use http::{StatusCode, header::WWW_AUTHENTICATE};
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+///
+/// async fn auth(request: Request, next: Next) -> Response {
+///     if !request.headers().contains_key("authorization") {
+///         return (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")]).into_response();
+///     }
+///     next.run(request).await
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+/// let router = Router::new().route("/", get(|| async {})).layer(middleware::from_fn(auth));
+/// let router = add_trace_id_middleware_with_config(router, config);
+///
+/// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+/// assert!(response.headers().contains_key("x-trace-id"));
+/// # }
+/// ```
+///
+/// # Compression
+///
+/// For the same reason, add `tower_http`'s `CompressionLayer` and `RequestDecompressionLayer`
+/// *after* this middleware, so they sit outside it: everything the span records and logs about
+/// bodies, such as a `Content-Length`, then describes the uncompressed payload the handler
+/// actually read and wrote, rather than what went over the wire.
+///
+/// ```rust,ignore
+/// let router = add_trace_id_middleware(router)
+///     .layer(CompressionLayer::new())
+///     .layer(RequestDecompressionLayer::new());
+/// ```
+///
+/// # Applying the middleware twice
+///
+/// If the middleware runs on a request it has already handled (e.g. both a library router and
+/// the app add it), the inner application keeps the outer trace ID and logs a warning once, so
+/// the duplicate can be removed from the layer stack.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceId, TraceIdConfig, add_trace_id_middleware, add_trace_id_middleware_with_config};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(|trace_id: TraceId| async move { trace_id.to_string() })));
+/// let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+/// let router = add_trace_id_middleware_with_config(router, config);
+///
+/// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// let echoed = response.headers()["x-trace-id"].to_str().unwrap().to_owned();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// assert_eq!(echoed.as_bytes(), body);
+/// # }
+/// ```
+///
+/// # Nested and merged routers
+///
+/// In a modular app, define the config once and apply the middleware once, to the top-level
+/// router, after `Router::nest` and `Router::merge`: the config is then the single source of
+/// truth for every module, and handlers read it with the [`ActiveConfig`] extractor. If modules
+/// also apply the middleware themselves, the outermost config wins, and a warning is logged once
+/// if their header name, [`IdFormat`] or [`SpanField`] differ from it.
+///
+/// ```rust
+/// use axum::{Router, routing::get};
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+///
+/// let users = Router::new().route("/", get(|| async {}));
+/// let orders = Router::new().route("/", get(|| async {}));
+///
+/// let config = TraceIdConfig::builder().header_name("x-request-id").build().unwrap();
+/// let app = Router::new().nest("/users", users).nest("/orders", orders);
+/// let app = add_trace_id_middleware_with_config(app, config);
+/// # let _: Router = app;
+/// ```
 pub fn add_trace_id_middleware(router: axum::Router) -> axum::Router {
-    router
-        // NOTE: It's required to use ServiceBuilder (rather than chain `.layer()` on router),
-        // since otherwise `TraceId` isn't exposed in the extensions
-        .layer(ServiceBuilder::new()
-            // inject trace_id into request extensions
-            .layer(middleware::from_fn(inject_trace_id))
-            // then add tracing layer
-            .layer(
-                TraceLayer::new_for_http()
-                    .make_span_with(|request: &Request<axum::body::Body>| {
-                        let span =
-                            tracing::info_span!("http-request", trace_id = tracing::field::Empty);
-
-                        // Get trace_id from extensions (already injected by previous middleware)
-                        if let Some(trace_id) = request.extensions().get::<TraceId>() {
-                            // Check if it came from header or was generated
-                            if request.headers().get(TRACE_ID_HEADER).is_some() {
-                                info!("Received request with trace_id: '{trace_id}'");
-                            } else {
-                                info!("Received request without trace_id. Assigned: '{trace_id}'");
-                            }
-
-                            span.record("trace_id", trace_id.to_string());
-                        } else {
-                            error!("Unable to recover TraceId?");
-                        }
-
-                        span
-                    })
-                    .on_response(
-                        |_response: &Response<axum::body::Body>, latency: Duration, _span: &Span| {
-                            tracing::debug!("response generated in '{latency:?}'")
-                        },
-                    )
-                    .on_eos(
-                        |_trailers: Option<&HeaderMap>, stream_duration: Duration, _span: &Span| {
-                            tracing::debug!("stream closed after '{stream_duration:?}'")
-                        },
-                    )
-                    .on_failure(
-                        |error: ServerErrorsFailureClass, latency: Duration, _span: &Span| {
-                            tracing::warn!(
-                                "something went wrong. Error data: '{error:?}'. Latency: '{latency:?}'"
-                            )
-                        },
-                    ),
-            ))
+    add_trace_id_middleware_with_config(router, TraceIdConfig::default())
+}
+
+/// Same as [`add_trace_id_middleware`], but configured by `config`.
+///
+/// # HTTP/2 and HTTP/3
+///
+/// Header names are lowercased when the config is built, as HTTP/2 and HTTP/3 require, so a
+/// configured name like `X-Request-ID` matches the header whatever the protocol. Values are
+/// neither split nor re-encoded, so a trace ID echoed over HTTP/2 is exactly what was sent.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::{Request, Version};
+/// use hyper_util::rt::{TokioExecutor, TokioIo};
+/// use tokio::net::{TcpListener, TcpStream};
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder()
+///     .header_name("X-Request-ID")
+///     .echo_header(true)
+///     .build()
+///     .unwrap();
+/// let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+///
+/// let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+/// let addr = listener.local_addr().unwrap();
+/// tokio::spawn(async move { axum::serve(listener, router).await });
+///
+/// // An HTTP/2 client with prior knowledge
+/// let stream = TcpStream::connect(addr).await.unwrap();
+/// let (mut sender, connection) =
+///     hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+///         .await
+///         .unwrap();
+/// tokio::spawn(connection);
+///
+/// let request = Request::get(format!("http://{addr}/"))
+///     .header("X-Request-ID", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = sender.send_request(request).await.unwrap();
+/// assert_eq!(response.version(), Version::HTTP_2);
+/// assert_eq!(response.headers()["x-request-id"], "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+/// # }
+/// ```
+pub fn add_trace_id_middleware_with_config(
+    router: axum::Router,
+    config: TraceIdConfig,
+) -> axum::Router {
+    add_trace_id_middleware_with_shared_config(router, config).0
+}
+
+/// Same as [`add_trace_id_middleware_with_config`], but also returns the config the middleware
+/// uses.
+///
+/// Keep it around for code outside of handlers that needs the same header name and format, e.g.
+/// to set trace IDs on outbound requests. Handlers can use the [`ActiveConfig`] extractor instead.
+///
+/// ```rust
+/// use axum::{Router, routing::get};
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_shared_config};
+///
+/// let config = TraceIdConfig::builder().header_name("x-request-id").build().unwrap();
+/// let (router, config) =
+///     add_trace_id_middleware_with_shared_config(Router::new().route("/", get(|| async {})), config);
+/// assert_eq!(config.header_name(), "x-request-id");
+/// # let _: Router = router;
+/// ```
+pub fn add_trace_id_middleware_with_shared_config(
+    router: axum::Router,
+    config: TraceIdConfig,
+) -> (axum::Router, Arc<TraceIdConfig>) {
+    let config = Arc::new(config);
+    let layers = ServiceBuilder::new()
+        // inject trace_id into request extensions
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            inject_trace_id,
+        ));
+    // then add tracing layer
+    #[cfg(feature = "tracing")]
+    let layers = layers
+        .layer(span::trace_layer(config.clone()))
+        .option_layer(
+            config
+                .covers_body()
+                .then(|| middleware::map_response_with_state(config.clone(), span::cover_body)),
+        );
+
+    // NOTE: It's required to use ServiceBuilder (rather than chain `.layer()` on router),
+    // since otherwise `TraceId` isn't exposed in the extensions
+    (router.layer(layers), config)
+}
+
+/// Same as [`add_trace_id_middleware_with_config`], but taking the config from the application
+/// state `S`, for routers that already keep it there. That's all it does: the config's callbacks
+/// don't get the state, so those that need part of it, e.g. a metrics handle for a
+/// [`source`](TraceIdConfigBuilder::source), capture their own clone when the config is built.
+///
+/// The middleware gets the config with `Arc<TraceIdConfig>: FromRef<S>` on every request, so
+/// implement [`FromRef`] by cloning an `Arc` kept in the state: it's also read once here, for the
+/// `tracing` span, so returning a different config per request isn't supported. `S` must be
+/// `Clone + Send + Sync + 'static`, like any axum state.
+///
+/// The returned router still needs its state, so call `Router::with_state` afterwards.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use axum::{Router, body::Body, extract::{FromRef, State}, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_state};
+///
+/// #[derive(Clone)]
+/// struct AppState {
+///     trace_id_config: Arc<TraceIdConfig>,
+///     greeting: &'static str,
+/// }
+///
+/// impl FromRef<AppState> for Arc<TraceIdConfig> {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.trace_id_config.clone()
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+/// let state = AppState { trace_id_config: Arc::new(config), greeting: "Hello" };
+///
+/// let router = Router::new().route("/", get(|State(state): State<AppState>| async move { state.greeting }));
+/// let router = add_trace_id_middleware_with_state(router, state.clone()).with_state(state);
+///
+/// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// assert!(response.headers().contains_key("x-trace-id"));
+/// # }
+/// ```
+pub fn add_trace_id_middleware_with_state<S>(router: axum::Router<S>, state: S) -> axum::Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<TraceIdConfig>: FromRef<S>,
+{
+    #[cfg(feature = "tracing")]
+    let config = Arc::<TraceIdConfig>::from_ref(&state);
+    let layers =
+        ServiceBuilder::new().layer(middleware::from_fn_with_state(state, inject_trace_id));
+    #[cfg(feature = "tracing")]
+    let layers = layers
+        .layer(span::trace_layer(config.clone()))
+        .option_layer(
+            config
+                .covers_body()
+                .then(|| middleware::map_response_with_state(config, span::cover_body)),
+        );
+
+    // NOTE: See add_trace_id_middleware_with_shared_config for why ServiceBuilder is required
+    router.layer(layers)
 }
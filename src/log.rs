@@ -0,0 +1,52 @@
+//! Internal logging macros.
+//!
+//! They forward to `tracing` with the `tracing` feature, and compile to nothing without it: the
+//! fields and format arguments are only borrowed, so the values logged still count as used, but
+//! nothing is formatted.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, error, info, warn};
+
+/// Logs at a [`Level`](tracing::Level) only known at runtime.
+///
+/// `tracing` needs the level of each callsite at compile time, so this expands to one callsite
+/// per level.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            tracing::Level::ERROR => tracing::error!($($arg)+),
+            tracing::Level::WARN => tracing::warn!($($arg)+),
+            tracing::Level::INFO => tracing::info!($($arg)+),
+            tracing::Level::DEBUG => tracing::debug!($($arg)+),
+            tracing::Level::TRACE => tracing::trace!($($arg)+),
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) use event;
+
+/// Takes the arguments of a `tracing` event, in the subset of its syntax this crate uses:
+/// `field = value`, with `%` or `?`, and `field` shorthands, then the message.
+#[cfg(not(feature = "tracing"))]
+macro_rules! noop {
+    () => {
+        ()
+    };
+    ($message:literal $(, $arg:expr)* $(,)?) => {{
+        let _ = format_args!($message $(, $arg)*);
+    }};
+    ($($field:ident).+ = $(%)? $(?)? $value:expr $(, $($rest:tt)*)?) => {{
+        let _ = &$value;
+        $crate::log::noop!($($($rest)*)?)
+    }};
+    ($(%)? $(?)? $field:ident $(, $($rest:tt)*)?) => {{
+        let _ = &$field;
+        $crate::log::noop!($($($rest)*)?)
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_imports)]
+pub(crate) use {noop, noop as debug, noop as error, noop as info, noop as warn};
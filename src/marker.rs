@@ -0,0 +1,99 @@
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use crate::{TraceId, log::warn};
+
+/// The length of a slot, newline included.
+const SLOT_LEN: usize = 64;
+
+/// The file set with
+/// [`TraceIdConfigBuilder::last_request_file`](crate::TraceIdConfigBuilder::last_request_file): a
+/// ring of fixed-width lines, each holding the sequence number and trace ID of a request that
+/// started.
+#[derive(Debug, Clone)]
+pub(crate) struct LastRequestFile(Arc<Ring>);
+
+#[derive(Debug)]
+struct Ring {
+    file: File,
+    slots: u64,
+    next: AtomicU64,
+    failed: AtomicBool,
+    /// Serializes the seek and the write, on platforms without positional writes.
+    #[cfg(not(any(unix, windows)))]
+    seek: std::sync::Mutex<()>,
+}
+
+impl LastRequestFile {
+    /// Creates or truncates the file at `path`, sized for `slots` lines. Slots not written yet
+    /// read as NUL bytes.
+    pub(crate) fn create(path: &Path, slots: usize) -> io::Result<Self> {
+        let slots = slots.max(1) as u64;
+        let file = File::create(path)?;
+        file.set_len(slots * SLOT_LEN as u64)?;
+        Ok(Self(Arc::new(Ring {
+            file,
+            slots,
+            next: AtomicU64::new(0),
+            failed: AtomicBool::new(false),
+            #[cfg(not(any(unix, windows)))]
+            seek: std::sync::Mutex::new(()),
+        })))
+    }
+
+    /// Writes `trace_id` to the next slot, with a single positional write, so concurrent requests
+    /// never write to the same slot unless the ring wrapped around. Trace IDs that don't fit are
+    /// truncated.
+    pub(crate) fn record(&self, trace_id: &TraceId) {
+        let ring = &self.0;
+        let seq = ring.next.fetch_add(1, Ordering::Relaxed);
+        let mut line = format!("{seq:016x} {trace_id}");
+        let mut len = line.len().min(SLOT_LEN - 1);
+        while !line.is_char_boundary(len) {
+            len -= 1;
+        }
+        line.truncate(len);
+        line.extend(std::iter::repeat_n(' ', SLOT_LEN - 1 - len));
+        line.push('\n');
+
+        let offset = (seq % ring.slots) * SLOT_LEN as u64;
+        if let Err(e) = ring.write_at(line.as_bytes(), offset)
+            && !ring.failed.swap(true, Ordering::Relaxed)
+        {
+            warn!(error = %e, "Unable to write the last request file, ignoring further errors");
+        }
+    }
+}
+
+impl Ring {
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(&self.file, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let written = std::os::windows::fs::FileExt::seek_write(&self.file, buf, offset)?;
+        if written < buf.len() {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let _seek = self.seek.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(buf)
+    }
+}
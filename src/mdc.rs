@@ -0,0 +1,38 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Future wrapper that sets the trace ID in the `log-mdc` context while it's polled.
+    pub(crate) struct WithMdc<F> {
+        #[pin]
+        inner: F,
+        key: &'static str,
+        trace_id: String,
+    }
+}
+
+impl<F> WithMdc<F> {
+    pub(crate) fn new(inner: F, key: &'static str, trace_id: String) -> Self {
+        Self {
+            inner,
+            key,
+            trace_id,
+        }
+    }
+}
+
+impl<F: Future> Future for WithMdc<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        // Restores the previous value (if any) once this poll is over
+        let _guard = log_mdc::insert_scoped(*this.key, this.trace_id.as_str());
+        this.inner.poll(cx)
+    }
+}
@@ -0,0 +1,105 @@
+//! Prometheus request metrics carrying the trace ID as an exemplar.
+//!
+//! Each sample links to an example trace, so operators can jump from a latency spike straight to
+//! a request's logs. Metrics are recorded with
+//! [`prometheus-client`](https://docs.rs/prometheus-client), and exemplars are only exposed in the
+//! OpenMetrics text format it encodes. Prometheus stores them with
+//! `--enable-feature=exemplar-storage`.
+//!
+//! ```rust
+//! use axum::{Router, body::Body, routing::get};
+//! use http::Request;
+//! use prometheus_client::{encoding::text::encode, registry::Registry};
+//! use tower::ServiceExt;
+//! use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config, metrics::RequestMetrics};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let metrics = RequestMetrics::new();
+//! let mut registry = Registry::default();
+//! metrics.register(&mut registry);
+//!
+//! let config = TraceIdConfig::builder().metrics(metrics).build().unwrap();
+//! let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+//! let request = Request::get("/")
+//!     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! router.oneshot(request).await.unwrap();
+//!
+//! let mut exposition = String::new();
+//! encode(&mut exposition, &registry).unwrap();
+//! // http_requests_total{status="200"} 1 # {trace_id="0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"} 1
+//! assert!(exposition.contains(r#"# {trace_id="0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"}"#));
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use http::StatusCode;
+use prometheus_client::{
+    metrics::{
+        exemplar::{CounterWithExemplar, HistogramWithExemplars},
+        family::Family,
+        histogram::exponential_buckets,
+    },
+    registry::Registry,
+};
+
+use crate::TraceId;
+
+type StatusLabels = [(&'static str, u16); 1];
+type TraceIdExemplar = [(&'static str, String); 1];
+
+/// Request count and duration, by response status, with the trace ID as exemplar.
+///
+/// Pass it to [`TraceIdConfigBuilder::metrics`](crate::TraceIdConfigBuilder::metrics) to record
+/// every request, and [`register`](Self::register) it to expose the metrics.
+#[derive(Debug, Clone)]
+pub struct RequestMetrics {
+    requests: Family<StatusLabels, CounterWithExemplar<TraceIdExemplar>>,
+    duration: Family<StatusLabels, HistogramWithExemplars<TraceIdExemplar>>,
+}
+
+impl RequestMetrics {
+    /// Creates the metrics, with duration buckets from 5ms to about 10s.
+    pub fn new() -> Self {
+        Self {
+            requests: Family::default(),
+            duration: Family::new_with_constructor(|| {
+                HistogramWithExemplars::new(exponential_buckets(0.005, 2.0, 12))
+            }),
+        }
+    }
+
+    /// Registers `http_requests_total` and `http_request_duration_seconds` in `registry`.
+    pub fn register(&self, registry: &mut Registry) {
+        registry.register(
+            "http_requests",
+            "Number of HTTP requests handled",
+            self.requests.clone(),
+        );
+        registry.register(
+            "http_request_duration_seconds",
+            "Time until the HTTP response was produced",
+            self.duration.clone(),
+        );
+    }
+
+    pub(crate) fn observe(&self, status: StatusCode, latency: Duration, trace_id: &TraceId) {
+        let labels = [("status", status.as_u16())];
+        let exemplar = [("trace_id", trace_id.to_string())];
+        self.requests
+            .get_or_create(&labels)
+            .inc_by(1, Some(exemplar.clone()), None);
+        self.duration
+            .get_or_create(&labels)
+            .observe(latency.as_secs_f64(), Some(exemplar), None);
+    }
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,143 @@
+use axum::extract::Request;
+use http::{HeaderName, HeaderValue};
+use tower::ServiceBuilder;
+use tower_http::request_id::{
+    MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use uuid::Uuid;
+
+use crate::{AccessLogField, TraceIdConfig, add_trace_id_middleware_with_config, log::warn};
+
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// A batteries-included preset: trace ID injection, a request ID and an access log line, in the
+/// right order.
+///
+/// Where [`add_trace_id_middleware`](crate::add_trace_id_middleware) is the minimal entry point,
+/// this gives a new service consistent observability in one call. [`apply`](Self::apply) adds,
+/// from the outermost layer in:
+///
+/// 1. trace ID injection and the `http-request` span, as configured by [`config`](Self::config),
+/// 2. with [`request_id`](Self::request_id), `tower_http`'s request ID layers: a new UUIDv7
+///    `x-request-id` is set on the request, replacing any a caller sent, as it identifies this
+///    hop while the trace ID spans them, and copied to the response. Handlers read it from the
+///    `tower_http::request_id::RequestId` extension,
+/// 3. the router.
+///
+/// With [`access_log`](Self::access_log), one line is logged per request at `INFO`, replacing the
+/// request lifecycle lines, unless the config already set a level. Its fields are those of
+/// [`TraceIdConfigBuilder::access_log_fields`](crate::TraceIdConfigBuilder::access_log_fields),
+/// plus `request_id`:
+///
+/// ```text
+/// INFO trace_id_layer::span: Request completed trace_id=0192f0c4-... method=GET path="/users" status=200 latency=1.2ms bytes=42 request_id="0192f0c4-..."
+/// ```
+///
+/// Trace ID injection is the core of the preset, so it can't be turned off; both other components
+/// are on by default.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::full_observability;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = full_observability().apply(Router::new().route("/", get(|| async {})));
+///
+/// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// // Logged with the access log line as `request_id`
+/// assert!(response.headers().contains_key("x-request-id"));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FullObservability {
+    config: TraceIdConfig,
+    request_id: bool,
+    access_log: bool,
+}
+
+/// Creates the [`FullObservability`] preset, with every component enabled.
+pub fn full_observability() -> FullObservability {
+    FullObservability {
+        config: TraceIdConfig::default(),
+        request_id: true,
+        access_log: true,
+    }
+}
+
+impl FullObservability {
+    /// Configures the trace ID middleware. Defaults to [`TraceIdConfig::default`].
+    ///
+    /// ```rust
+    /// use trace_id_layer::{TraceIdConfig, full_observability};
+    ///
+    /// let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+    /// let preset = full_observability().config(config);
+    /// ```
+    pub fn config(mut self, config: TraceIdConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Whether to set and propagate an `x-request-id`.
+    ///
+    /// Skipped, with a warning, if the [config](Self::config) reads trace IDs from
+    /// `x-request-id`, as the request ID would replace the trace ID.
+    pub fn request_id(mut self, request_id: bool) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Whether to log the access log line.
+    pub fn access_log(mut self, access_log: bool) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    /// Adds the enabled components to `router`.
+    pub fn apply(self, router: axum::Router) -> axum::Router {
+        let mut config = self.config;
+        let request_id = self.request_id && config.header_name != X_REQUEST_ID;
+        if self.request_id && !request_id {
+            warn!("The trace ID header is x-request-id, not adding the request ID layers");
+        }
+        if self.access_log {
+            config.access_log.get_or_insert(tracing::Level::INFO);
+            if request_id
+                && !config
+                    .access_log_fields
+                    .contains(&AccessLogField::RequestId)
+            {
+                config.access_log_fields.push(AccessLogField::RequestId);
+            }
+        }
+        if !request_id {
+            return add_trace_id_middleware_with_config(router, config);
+        }
+        let request_id = ServiceBuilder::new()
+            .map_request(remove_request_id)
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestV7))
+            .layer(PropagateRequestIdLayer::x_request_id());
+        add_trace_id_middleware_with_config(router.layer(request_id), config)
+    }
+}
+
+/// Drops an inbound `x-request-id`, so [`SetRequestIdLayer`] always sets a new one.
+fn remove_request_id(mut request: Request) -> Request {
+    request.headers_mut().remove(X_REQUEST_ID);
+    request
+}
+
+/// Generates request IDs as UUIDv7s, like trace IDs.
+#[derive(Clone, Copy)]
+struct MakeRequestV7;
+
+impl MakeRequestId for MakeRequestV7 {
+    fn make_request_id<B>(&mut self, _request: &Request<B>) -> Option<RequestId> {
+        HeaderValue::from_str(&Uuid::now_v7().to_string())
+            .ok()
+            .map(RequestId::new)
+    }
+}
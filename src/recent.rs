@@ -0,0 +1,109 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use axum::response::{IntoResponse, Response};
+use http::{StatusCode, header::CONTENT_TYPE};
+
+use crate::{ActiveConfig, AuditRecord, fallback::json_escape};
+
+/// The ring buffer set with
+/// [`TraceIdConfigBuilder::recent_requests`](crate::TraceIdConfigBuilder::recent_requests).
+#[derive(Debug, Clone)]
+pub(crate) struct RecentRequests {
+    capacity: usize,
+    records: Arc<Mutex<VecDeque<AuditRecord>>>,
+}
+
+impl RecentRequests {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Adds `record`, evicting the oldest one when full.
+    pub(crate) fn record(&self, record: AuditRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The records as a JSON array, newest first.
+    fn to_json(&self) -> String {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let mut json = String::from("[");
+        for (i, record) in records.iter().rev().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                r#"{{"trace_id":"{}","method":"{}","path":"{}","status":{},"latency_ms":{:.3}}}"#,
+                json_escape(&record.trace_id.to_string()),
+                record.method,
+                json_escape(&record.path),
+                record.status.as_u16(),
+                record.latency.as_secs_f64() * 1000.0,
+            );
+        }
+        json.push(']');
+        json
+    }
+}
+
+/// A debug handler listing the requests recorded with
+/// [`recent_requests`](crate::TraceIdConfigBuilder::recent_requests), newest first, as JSON.
+///
+/// Mount it on an internal route of the router the middleware wraps, since it reads the buffer
+/// from the [`ActiveConfig`]. It answers `404 Not Found` if recording isn't enabled.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config, recent_requests_handler};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder().recent_requests(100).build().unwrap();
+/// let router = Router::new()
+///     .route("/users", get(|| async {}))
+///     .route("/internal/recent", get(recent_requests_handler));
+/// let router = add_trace_id_middleware_with_config(router, config);
+///
+/// let request = Request::get("/users")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.clone().oneshot(request).await.unwrap();
+///
+/// let request = Request::get("/internal/recent").body(Body::empty()).unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+/// let body = std::str::from_utf8(&body).unwrap();
+/// // [{"trace_id":"0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e","method":"GET","path":"/users","status":200,"latency_ms":0.042}]
+/// assert!(body.starts_with(r#"[{"trace_id":"0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e","method":"GET","path":"/users","status":200,"#));
+/// # }
+/// ```
+pub async fn recent_requests_handler(config: ActiveConfig) -> Response {
+    const ERR_MSG: &str =
+        "Recent requests aren't recorded. Did you enable TraceIdConfigBuilder::recent_requests?";
+    match &config.recent_requests {
+        Some(recent) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, "application/json")],
+            recent.to_json(),
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, ERR_MSG).into_response(),
+    }
+}
@@ -0,0 +1,155 @@
+//! A standalone layer rejecting requests without a valid trace ID.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{HeaderName, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use tower::{Layer, Service};
+
+use crate::{TRACE_ID_HEADER, core, log::warn};
+
+/// Rejects requests whose trace ID header is missing or invalid with `400 Bad Request` and an
+/// empty body, and passes the others through untouched.
+///
+/// Unlike [`require_inbound`](crate::TraceIdConfigBuilder::require_inbound), which is part of the
+/// full middleware, this layer only validates: it doesn't generate trace IDs, insert extensions,
+/// create spans or set response headers, and rejects invalid headers too. Use it in front of
+/// services whose tracing setup already handles trace IDs, including non-axum tower services.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::{Request, StatusCode};
+/// use tower::ServiceExt;
+/// use trace_id_layer::RequireTraceIdLayer;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = Router::new().route("/", get(|| async {})).layer(RequireTraceIdLayer::new());
+///
+/// let response = router.clone().oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+///
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// let response = router.oneshot(request).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::OK);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireTraceIdLayer {
+    header_name: HeaderName,
+}
+
+impl RequireTraceIdLayer {
+    pub fn new() -> Self {
+        Self {
+            header_name: TRACE_ID_HEADER,
+        }
+    }
+
+    /// Sets the header the trace ID is read from. Defaults to `x-trace-id`.
+    pub fn header_name(mut self, header_name: HeaderName) -> Self {
+        self.header_name = header_name;
+        self
+    }
+}
+
+impl Default for RequireTraceIdLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for RequireTraceIdLayer {
+    type Service = RequireTraceId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireTraceId {
+            inner,
+            header_name: self.header_name.clone(),
+        }
+    }
+}
+
+/// The service added by [`RequireTraceIdLayer`].
+#[derive(Debug, Clone)]
+pub struct RequireTraceId<S> {
+    inner: S,
+    header_name: HeaderName,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequireTraceId<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        match core::extract_header(request.headers(), &self.header_name) {
+            Ok(Some(_)) => ResponseFuture::Inner {
+                future: self.inner.call(request),
+            },
+            Ok(None) => {
+                warn!("Rejecting request without trace_id");
+                ResponseFuture::rejected()
+            }
+            Err(e) => {
+                warn!(error = %e, "Rejecting request with invalid trace_id");
+                ResponseFuture::rejected()
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`RequireTraceId`].
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, B> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B: Default> ResponseFuture<F, B> {
+    fn rejected() -> Self {
+        let mut response = Response::new(B::default());
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        Self::Rejected {
+            response: Some(response),
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner { future } => future.poll(cx),
+            ResponseFutureProj::Rejected { response } => Poll::Ready(Ok(response
+                .take()
+                .expect("ResponseFuture polled after completion"))),
+        }
+    }
+}
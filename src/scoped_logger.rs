@@ -0,0 +1,35 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Future wrapper that makes `logger` the `slog-scope` logger while it's polled.
+    pub(crate) struct WithSlogScope<F> {
+        #[pin]
+        inner: F,
+        logger: Option<slog::Logger>,
+    }
+}
+
+impl<F> WithSlogScope<F> {
+    pub(crate) fn new(inner: F, logger: Option<slog::Logger>) -> Self {
+        Self { inner, logger }
+    }
+}
+
+impl<F: Future> Future for WithSlogScope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.logger {
+            // The previous scope is restored once this poll is over
+            Some(logger) => slog_scope::scope(logger, || this.inner.poll(cx)),
+            None => this.inner.poll(cx),
+        }
+    }
+}
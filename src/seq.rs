@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::extract::FromRequestParts;
+use http::{StatusCode, request::Parts};
+
+use crate::log::error;
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A per-process request sequence number, incremented for every request.
+///
+/// Orders logs from a single instance even when clock resolution is too coarse to. Enable it with
+/// [`request_seq`](crate::TraceIdConfigBuilder::request_seq); it's then recorded as the
+/// `request_seq` span field and available as an extractor:
+///
+/// ```rust
+/// use trace_id_layer::{RequestSeq, TraceId};
+///
+/// async fn my_handler(trace_id: TraceId, seq: RequestSeq) -> String {
+///     format!("request #{} ({trace_id})", seq.get())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequestSeq(u64);
+
+impl RequestSeq {
+    pub(crate) fn next() -> Self {
+        Self(NEXT_SEQ.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestSeq
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "RequestSeq extension missing. Did you enable TraceIdConfigBuilder::request_seq?";
+        parts
+            .extensions
+            .get::<RequestSeq>()
+            .copied()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
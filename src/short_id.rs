@@ -0,0 +1,46 @@
+//! Short, human-typable trace IDs in Crockford base32.
+
+use uuid::Uuid;
+
+use crate::TraceId;
+
+/// Crockford's base32 alphabet: no `I`, `L`, `O` or `U`, to avoid ambiguous characters.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The longest short ID, using all 120 random bits [`generate`] draws.
+pub(crate) const MAX_LEN: usize = 24;
+
+/// Generates a random short ID of `len` characters.
+pub(crate) fn generate(len: usize) -> TraceId {
+    // The low 62 bits of a UUIDv4 are random (the version and variant are above), 60 of them
+    // are used so two draws fill 120 bits, a multiple of the 5 bits per character
+    const RANDOM_BITS: u128 = (1 << 60) - 1;
+    let mut random =
+        (Uuid::new_v4().as_u128() & RANDOM_BITS) | (Uuid::new_v4().as_u128() & RANDOM_BITS) << 60;
+    let id = (0..len)
+        .map(|_| {
+            let char = ALPHABET[(random & 0x1f) as usize] as char;
+            random >>= 5;
+            char
+        })
+        .collect::<String>();
+    TraceId::from_text(id)
+}
+
+/// Parses a short ID of `len` characters, normalized to uppercase, with the characters Crockford
+/// base32 decodes leniently (`I`/`L` as `1`, `O` as `0`) replaced.
+pub(crate) fn parse(value: &str, len: usize) -> Option<TraceId> {
+    if value.len() != len {
+        return None;
+    }
+    value
+        .chars()
+        .map(|char| match char.to_ascii_uppercase() {
+            'I' | 'L' => Some('1'),
+            'O' => Some('0'),
+            char if char.is_ascii() && ALPHABET.contains(&(char as u8)) => Some(char),
+            _ => None,
+        })
+        .collect::<Option<String>>()
+        .map(TraceId::from_text)
+}
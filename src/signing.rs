@@ -0,0 +1,63 @@
+use std::fmt::{self, Debug};
+
+use hmac::{Hmac, KeyInit, Mac};
+use http::{HeaderMap, HeaderName};
+use sha2::Sha256;
+
+use crate::TraceId;
+
+/// The header carrying the HMAC signature of the trace ID, when
+/// [signing](crate::TraceIdConfigBuilder::signing_key) is enabled.
+pub const TRACE_ID_SIG_HEADER: HeaderName = HeaderName::from_static("x-trace-id-sig");
+
+/// An HMAC-SHA256 key signing and verifying trace IDs.
+#[derive(Clone)]
+pub(crate) struct SigningKey(Hmac<Sha256>);
+
+impl SigningKey {
+    pub(crate) fn new(key: &[u8]) -> Self {
+        Self(Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"))
+    }
+
+    /// The hex-encoded signature of `trace_id`.
+    pub(crate) fn sign(&self, trace_id: &TraceId) -> String {
+        crate::hex(&self.mac(trace_id).finalize().into_bytes())
+    }
+
+    /// Checks the signature sent alongside `trace_id`, in constant time.
+    pub(crate) fn verify(&self, trace_id: &TraceId, headers: &HeaderMap) -> bool {
+        let Some(signature) = headers
+            .get(TRACE_ID_SIG_HEADER)
+            .and_then(|value| decode_hex(value.as_bytes()))
+        else {
+            return false;
+        };
+        self.mac(trace_id).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, trace_id: &TraceId) -> Hmac<Sha256> {
+        let mut mac = self.0.clone();
+        mac.update(trace_id.to_string().as_bytes());
+        mac
+    }
+}
+
+// Never print the key
+impl Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SigningKey(..)")
+    }
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    // `from_str_radix` alone would take a sign, as in `+f`
+    if !hex.len().is_multiple_of(2) || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    hex.chunks_exact(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
@@ -0,0 +1,536 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{body::Body, extract::State, middleware::Next, response::Response};
+use http::{HeaderMap, HeaderName, Method, Request, StatusCode, header::CONTENT_RANGE};
+use tokio::task::JoinHandle;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, ServerErrorsFailureClass, SharedClassifier},
+    trace::{
+        DefaultOnBodyChunk, DefaultOnRequest, MakeSpan, OnFailure, OnRequest, OnResponse,
+        TraceLayer,
+    },
+};
+use tracing::{Instrument, Level, Span};
+
+use crate::{
+    AccessLogField, CorrelationContext, HopCount, IdempotencyKey, ParentId, RequestSeq, SpanField,
+    TraceId, TraceIdConfig, TraceIdSource,
+    body::EosLog,
+    log::{debug, error, event, warn},
+};
+
+/// Creates an `INFO` span recording the [`current_trace_id`](crate::current_trace_id) as its own
+/// `trace_id` field, followed by any `tracing` span fields.
+///
+/// Spans created while a request is handled, e.g. with `#[instrument]`, are already children of
+/// the `http-request` span: formatters rendering the span list, such as
+/// `tracing_subscriber::fmt`, show its `trace_id` on every line, and OpenTelemetry exports them in
+/// the same trace. But fields aren't inherited, so backends indexing each span on its own fields
+/// don't see the trace ID on child spans. Create those spans with this macro instead, or record
+/// `trace_id = ?trace_id_layer::current_trace_id()` in `#[instrument(fields(...))]`.
+///
+/// The field is always named `trace_id`, whatever the configured [`SpanField`](crate::SpanField),
+/// and empty outside a request.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{add_trace_id_middleware, child_span};
+///
+/// async fn handler() {
+///     let user_id = 42;
+///     let _span = child_span!("load_user", user_id).entered();
+///     tracing::info!("loading user");
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.oneshot(request).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-...}:load_user{trace_id=0192f0c4-... user_id=42}: rust_out: loading user
+/// # }
+/// ```
+#[macro_export]
+macro_rules! child_span {
+    ($name:expr $(, $($fields:tt)*)?) => {
+        $crate::__tracing::info_span!(
+            $name,
+            trace_id = $crate::current_trace_id().map($crate::__tracing::field::display)
+            $(, $($fields)*)?
+        )
+    };
+}
+
+/// Declares the fields of the `http-request` span, for
+/// [`TraceIdConfigBuilder::span_fields`](crate::TraceIdConfigBuilder::span_fields).
+///
+/// `tracing` only records fields declared when a span is created, so fields can't be added to the
+/// span later on. This macro declares a custom set up front, all empty, which the layer and your
+/// code then fill in with [`Span::record`](tracing::Span::record). The layer records the fields
+/// it knows by name, and ignores the others:
+///
+/// - the trace ID under the configured [`SpanField`](crate::SpanField), e.g. `trace_id`,
+/// - `method` and `uri` of the request, and `status` of the response,
+/// - its built-in fields, such as `parent_id`, `outcome` or `error.kind`, if declared.
+///
+/// The tradeoff is that the field set is fixed at compile time: a field left out can't be
+/// recorded, and a field declared but never recorded is just left empty. Fields are also recorded
+/// right after the span is created, so unlike the default span, span-open events don't carry
+/// them.
+///
+/// ```rust
+/// use axum::{Router, body::Body, extract::MatchedPath, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config, trace_id_span_fields};
+///
+/// async fn handler(path: MatchedPath) {
+///     tracing::Span::current().record("route", path.as_str());
+///     tracing::info!("loading user");
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let config = TraceIdConfig::builder()
+///     .span_fields(trace_id_span_fields!(trace_id, method, route, status))
+///     .build()
+///     .unwrap();
+/// let router = Router::new().route("/users/{id}", get(handler));
+/// let router = add_trace_id_middleware_with_config(router, config);
+/// let request = Request::get("/users/42")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.oneshot(request).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-... method=GET route="/users/{id}"}: rust_out: loading user
+/// # }
+/// ```
+#[macro_export]
+macro_rules! trace_id_span_fields {
+    ($($($key:ident).+),+ $(,)?) => {
+        |_| $crate::__tracing::info_span!(
+            "http-request",
+            $($($key).+ = $crate::__tracing::field::Empty),+
+        )
+    };
+}
+
+/// Spawns `future` on the tokio runtime as a child of the current span, usually the
+/// `http-request` span.
+///
+/// Unlike a plain `tokio::spawn`, everything the task logs carries the trace ID, and trace
+/// viewers show it nested under the request with its own timing, in a `spawned` span.
+///
+/// The child span keeps its parent open: if the task outlives the request, the `http-request`
+/// span only closes once the task completes, so its close event and `time.busy`/`time.idle`
+/// include the background work. Only spawn work that's actually part of the request this way;
+/// for long-running jobs, copy the [`TraceId`] into them instead.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{add_trace_id_middleware, spawn_traced};
+///
+/// async fn handler() {
+///     spawn_traced(async { tracing::info!("sending email") }).await.unwrap();
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+/// let request = Request::get("/")
+///     .header("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e")
+///     .body(Body::empty())
+///     .unwrap();
+/// router.oneshot(request).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}:spawned: rust_out: sending email
+/// # }
+/// ```
+pub fn spawn_traced<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future.instrument(tracing::info_span!("spawned")))
+}
+
+/// When the trace ID middleware handed the request off to the inner layers.
+#[derive(Clone, Copy)]
+pub(crate) struct Handoff(pub(crate) Instant);
+
+/// The [fallback header](crate::TraceIdConfigBuilder::fallback_headers) the trace ID was read
+/// from, recorded as the `trace_id.header` span field.
+#[derive(Clone)]
+pub(crate) struct FallbackHeader(pub(crate) HeaderName);
+
+/// Records the time spent in middleware, from the trace ID middleware handing the request off
+/// until the handler runs, as the `middleware_latency` span field.
+///
+/// Add it with `route_layer`, so it runs last, right before the handler. Compared to the total
+/// latency logged with the response, it tells whether slowness is in middleware (auth, rate
+/// limiting, ...) or in the handler itself.
+///
+/// ```rust
+/// use axum::{Router, body::Body, middleware, routing::get};
+/// use http::Request;
+/// use tower::ServiceExt;
+/// use trace_id_layer::{add_trace_id_middleware, record_middleware_latency};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let router = Router::new()
+///     .route("/", get(|| async { tracing::info!("handling") }))
+///     .route_layer(middleware::from_fn(record_middleware_latency));
+/// let router = add_trace_id_middleware(router);
+/// router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+///
+/// // INFO http-request{trace_id=0192f0c4-... middleware_latency=12.3µs}: rust_out: handling
+/// # }
+/// ```
+pub async fn record_middleware_latency(request: Request<Body>, next: Next) -> Response {
+    if let Some(Handoff(start)) = request.extensions().get::<Handoff>() {
+        Span::current().record("middleware_latency", tracing::field::debug(start.elapsed()));
+    }
+    next.run(request).await
+}
+
+/// Logs the single [access log](crate::TraceIdConfigBuilder::access_log) line for a request.
+pub(crate) fn log_access(
+    level: Level,
+    config: &TraceIdConfig,
+    trace_id: &TraceId,
+    (method, path): (&Method, &str),
+    response: &Response<Body>,
+    latency: Duration,
+) {
+    let has = |field| config.access_log_fields.contains(&field);
+    let bytes = http_body::Body::size_hint(response.body()).exact();
+    event!(
+        level,
+        trace_id = has(AccessLogField::TraceId).then(|| tracing::field::display(trace_id)),
+        method = has(AccessLogField::Method).then(|| tracing::field::display(method)),
+        path = has(AccessLogField::Path).then_some(path),
+        status = has(AccessLogField::Status).then_some(response.status().as_u16()),
+        latency = has(AccessLogField::Latency).then(|| tracing::field::debug(latency)),
+        bytes = bytes.filter(|_| has(AccessLogField::Bytes)),
+        resource_bytes = has(AccessLogField::ResourceBytes)
+            .then(|| resource_bytes(response.headers()))
+            .flatten(),
+        request_id = has(AccessLogField::RequestId)
+            .then(|| response.headers().get(X_REQUEST_ID)?.to_str().ok())
+            .flatten(),
+        "Request completed"
+    );
+}
+
+/// The header `tower_http`'s request ID layers use.
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The complete length in a `Content-Range: bytes <range>/<length>` header, unless it's `*`.
+fn resource_bytes(headers: &HeaderMap) -> Option<u64> {
+    let content_range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (_, length) = content_range.strip_prefix("bytes ")?.rsplit_once('/')?;
+    length.trim().parse().ok()
+}
+
+/// Logs the trace ID if the request future is dropped before a response was produced.
+///
+/// That happens when a timeout layer above this middleware fires, or when the client disconnects.
+/// By then the `http-request` span is gone too, so the trace ID is logged explicitly.
+pub(crate) struct CancellationGuard {
+    trace_id: Option<TraceId>,
+    start: Instant,
+}
+
+impl CancellationGuard {
+    pub(crate) fn new(trace_id: TraceId) -> Self {
+        Self {
+            trace_id: Some(trace_id),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn complete(mut self) {
+        self.trace_id = None;
+    }
+}
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if let Some(trace_id) = &self.trace_id {
+            warn!(
+                trace_id = %trace_id,
+                elapsed = ?self.start.elapsed(),
+                "Request cancelled (timeout or client disconnect)"
+            );
+        }
+    }
+}
+
+/// The `tower_http` [`TraceLayer`] creating and logging the `http-request` span.
+pub(crate) fn trace_layer(
+    config: Arc<TraceIdConfig>,
+) -> TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    impl MakeSpan<Body> + Clone,
+    impl OnRequest<Body> + Clone,
+    impl OnResponse<Body> + Clone,
+    DefaultOnBodyChunk,
+    (),
+    impl OnFailure<ServerErrorsFailureClass> + Clone,
+> {
+    let span_config = config.clone();
+    let request_config = config.clone();
+    let failure_config = config.clone();
+    TraceLayer::new_for_http()
+        .make_span_with(move |request: &Request<Body>| make_span(request, &span_config))
+        .on_request(move |request: &Request<Body>, span: &Span| {
+            if !request_config.logs_access() {
+                DefaultOnRequest::new().on_request(request, span)
+            }
+        })
+        .on_response(
+            move |response: &Response<Body>, latency: Duration, span: &Span| {
+                span.record("outcome", Outcome::from_status(response.status()).as_str());
+                span.record("status", response.status().as_u16());
+                if let Some(attributes) = config.response_attributes(response) {
+                    span.record("response_attributes", attributes);
+                }
+                if !config.logs_access() {
+                    debug!(latency = ?latency, "response generated")
+                }
+            },
+        )
+        // Logged by the `EosLog` body instead, which knows the trace ID
+        .on_eos(())
+        .on_failure(
+            move |error: ServerErrorsFailureClass, latency: Duration, span: &Span| {
+                span.record("outcome", Outcome::ServerError.as_str());
+                match &error {
+                    ServerErrorsFailureClass::StatusCode(status) => {
+                        span.record("error.kind", "status");
+                        span.record("error.message", status.to_string());
+                    }
+                    ServerErrorsFailureClass::Error(message) => {
+                        span.record("error.kind", "error");
+                        span.record("error.message", message.as_str());
+                    }
+                }
+                if !failure_config.logs_access() {
+                    warn!(error = ?error, latency = ?latency, "something went wrong")
+                }
+            },
+        )
+}
+
+/// Handles the end of the response stream within the `http-request` span, for
+/// [`span_covers_body`](crate::TraceIdConfigBuilder::span_covers_body).
+///
+/// Runs inside the `TraceLayer`, which enters the span whenever the body is polled.
+pub(crate) async fn cover_body(
+    State(config): State<Arc<TraceIdConfig>>,
+    response: Response,
+) -> Response {
+    let Some(trace_id) = crate::current_trace_id() else {
+        return response;
+    };
+    let span = Span::current();
+    response.map(|body| Body::new(EosLog::new(body, trace_id, config.eos_level).in_span(span)))
+}
+
+/// Low-cardinality request outcome, recorded as the `outcome` span field.
+///
+/// Matches the `TraceLayer` failure classification: only server errors are failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    ClientError,
+    ServerError,
+}
+
+impl Outcome {
+    pub(crate) fn from_status(status: StatusCode) -> Self {
+        if status.is_server_error() {
+            Outcome::ServerError
+        } else if status.is_client_error() {
+            Outcome::ClientError
+        } else {
+            Outcome::Success
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::ClientError => "client_error",
+            Outcome::ServerError => "server_error",
+        }
+    }
+}
+
+/// The age of the inbound trace ID in milliseconds, from its UUIDv7 timestamp, for
+/// [`record_inbound_age`](crate::TraceIdConfigBuilder::record_inbound_age).
+fn inbound_age(request: &Request<Body>, config: &TraceIdConfig) -> Option<i64> {
+    let extensions = request.extensions();
+    let inbound = match extensions.get::<ParentId>() {
+        Some(parent_id) => parent_id,
+        None => match extensions.get::<TraceIdSource>()? {
+            TraceIdSource::Header | TraceIdSource::Fallback => extensions.get::<TraceId>()?,
+            _ => return None,
+        },
+    };
+    let age = match config.now().duration_since(inbound.timestamp()?) {
+        Ok(age) => age.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    };
+    Some(age)
+}
+
+/// Creates the `http-request` span for a request, with the trace ID recorded under the
+/// configured [`SpanField`].
+fn make_span(request: &Request<Body>, config: &TraceIdConfig) -> Span {
+    if config.skip_methods.contains(request.method()) {
+        return Span::none();
+    }
+    // Get trace_id from extensions (already injected by previous middleware)
+    let trace_id = request.extensions().get::<TraceId>();
+    let parent_id = request.extensions().get::<ParentId>();
+    let request_seq = request
+        .extensions()
+        .get::<RequestSeq>()
+        .map(RequestSeq::get);
+    let idempotency_key = request
+        .extensions()
+        .get::<IdempotencyKey>()
+        .map(|key| &**key);
+    let hops = request.extensions().get::<HopCount>().map(HopCount::get);
+    let fallback_header = request
+        .extensions()
+        .get::<FallbackHeader>()
+        .map(|FallbackHeader(header_name)| header_name.as_str());
+    let version = config
+        .record_http_version
+        .then(|| tracing::field::debug(request.version()));
+    let inbound_age = config
+        .record_inbound_age
+        .then(|| inbound_age(request, config))
+        .flatten();
+
+    // Record trace_id at creation, so span-open events already carry it
+    let value = trace_id.map(tracing::field::display);
+    let parent_value = parent_id.map(tracing::field::display);
+    let attributes = Some(config.span_attributes.as_str()).filter(|a| !a.is_empty());
+    let correlation = request
+        .extensions()
+        .get::<CorrelationContext>()
+        .and_then(CorrelationContext::span_value);
+    macro_rules! request_span {
+        ($($key:ident).+) => {
+            tracing::info_span!(
+                "http-request",
+                $($key).+ = value,
+                parent_id = parent_value,
+                request_seq,
+                idempotency_key,
+                hops,
+                trace_id.header = fallback_header,
+                http.version = version,
+                inbound_age_ms = inbound_age,
+                attributes,
+                correlation,
+                outcome = tracing::field::Empty,
+                middleware_latency = tracing::field::Empty,
+                response_attributes = tracing::field::Empty,
+                error.kind = tracing::field::Empty,
+                error.message = tracing::field::Empty,
+                body.bytes = tracing::field::Empty,
+                body.duration_ms = tracing::field::Empty,
+            )
+        };
+    }
+    let span = match &config.span_fields {
+        Some(span_fields) => {
+            let span = span_fields.call(request);
+            span.record(config.span_field.as_str(), &value);
+            span.record("method", tracing::field::display(request.method()));
+            span.record("uri", tracing::field::display(request.uri()));
+            span.record("parent_id", &parent_value);
+            span.record("request_seq", request_seq);
+            span.record("idempotency_key", idempotency_key);
+            span.record("hops", hops);
+            span.record("trace_id.header", fallback_header);
+            span.record("http.version", &version);
+            span.record("inbound_age_ms", inbound_age);
+            span.record("attributes", attributes);
+            span.record("correlation", &correlation);
+            span
+        }
+        None => match config.span_field {
+            SpanField::TraceId => request_span!(trace_id),
+            SpanField::CamelCase => request_span!(traceId),
+            SpanField::Datadog => request_span!(dd.trace_id),
+            SpanField::Ecs => request_span!(trace.id),
+        },
+    };
+
+    let Some(trace_id) = trace_id else {
+        // Nothing is inserted in dry-run mode
+        if !config.dry_run {
+            error!("Unable to recover TraceId?");
+        }
+        return span;
+    };
+
+    // Whether it came from the header or was generated is recorded by the injection middleware,
+    // so the configured header name is honoured without looking at the headers again. The access
+    // log line replaces these.
+    if !config.logs_access() {
+        let level = config.request_log_level(request.method(), Level::INFO);
+        span.in_scope(
+            || match (parent_id, request.extensions().get::<TraceIdSource>()) {
+                (Some(parent_id), _) => event!(
+                    level,
+                    trace_id = %trace_id,
+                    parent_id = %parent_id,
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    "Received request with parent_id"
+                ),
+                (
+                    None,
+                    Some(TraceIdSource::Header | TraceIdSource::Fallback | TraceIdSource::Custom),
+                ) => event!(
+                    level,
+                    trace_id = %trace_id,
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    "Received request with trace_id"
+                ),
+                _ => event!(
+                    level,
+                    trace_id = %trace_id,
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    "Received request without trace_id"
+                ),
+            },
+        );
+    }
+    if let Some(template) = &config.log_url {
+        span.in_scope(|| debug!(url = %trace_id.log_url(template), "Request logs"));
+    }
+
+    span
+}
@@ -0,0 +1,153 @@
+//! `tracing-subscriber` integration that puts the trace ID on error logs.
+//!
+//! The trace ID lives on the `http-request` span, so whether it shows up on an event depends on
+//! the formatter rendering spans. [`WithTraceId`] prefixes events (errors, by default) with
+//! `trace_id=<id>` regardless of span rendering, using the value captured by [`TraceIdFields`]:
+//!
+//! ```rust
+//! use tracing_subscriber::{fmt, prelude::*};
+//! use trace_id_layer::subscriber::{TraceIdFields, WithTraceId};
+//!
+//! tracing_subscriber::registry()
+//!     .with(TraceIdFields)
+//!     .with(fmt::layer().event_format(WithTraceId::new(fmt::format())))
+//!     .init();
+//! ```
+//!
+//! This targets text formatters; JSON formatters already include span fields via
+//! `with_current_span`/`with_span_list`.
+//!
+//! Requires the `subscriber` feature.
+
+use std::fmt::{self, Debug};
+
+use tracing::{
+    Event, Id, Level, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Record},
+};
+use tracing_subscriber::{
+    fmt::{FmtContext, FormatEvent, FormatFields, format::Writer},
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::SpanField;
+
+/// Captures the trace ID recorded on spans, for [`WithTraceId`] to render.
+///
+/// Recognizes every [`SpanField`] key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceIdFields;
+
+/// The trace ID captured from a span's fields, stored in the span's extensions.
+struct RecordedTraceId(String);
+
+impl<S> Layer<S> for TraceIdFields
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = TraceIdVisitor(None);
+        attrs.record(&mut visitor);
+        store(visitor, id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = TraceIdVisitor(None);
+        values.record(&mut visitor);
+        store(visitor, id, &ctx);
+    }
+}
+
+fn store<S>(visitor: TraceIdVisitor, id: &Id, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if let (Some(trace_id), Some(span)) = (visitor.0, ctx.span(id)) {
+        span.extensions_mut().replace(RecordedTraceId(trace_id));
+    }
+}
+
+struct TraceIdVisitor(Option<String>);
+
+impl Visit for TraceIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if is_trace_id_field(field) {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if is_trace_id_field(field) {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+fn is_trace_id_field(field: &Field) -> bool {
+    [
+        SpanField::TraceId,
+        SpanField::CamelCase,
+        SpanField::Datadog,
+        SpanField::Ecs,
+    ]
+    .iter()
+    .any(|span_field| span_field.as_str() == field.name())
+}
+
+/// Wraps an event formatter, prefixing events at or above a level with `trace_id=<id>`.
+///
+/// The trace ID is taken from the closest span in the event's scope that carries one, as captured
+/// by [`TraceIdFields`] (which must be part of the subscriber). Events outside a request span are
+/// formatted unchanged.
+#[derive(Debug, Clone)]
+pub struct WithTraceId<F> {
+    inner: F,
+    level: Level,
+}
+
+impl<F> WithTraceId<F> {
+    /// Prefixes `ERROR` events formatted by `inner`.
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            level: Level::ERROR,
+        }
+    }
+
+    /// Prefixes events at `level` or more severe, instead of only `ERROR`.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for WithTraceId<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        // More severe levels compare as smaller
+        if *event.metadata().level() <= self.level
+            && let Some(scope) = ctx.event_scope()
+        {
+            for span in scope {
+                if let Some(RecordedTraceId(trace_id)) = span.extensions().get::<RecordedTraceId>()
+                {
+                    write!(writer, "trace_id={trace_id} ")?;
+                    break;
+                }
+            }
+        }
+
+        self.inner.format_event(ctx, writer, event)
+    }
+}
@@ -0,0 +1,58 @@
+//! Assertions for tests of services using the middleware.
+//!
+//! They read the trace ID echoed in responses, so the middleware must be configured with
+//! [`echo_header`](crate::TraceIdConfigBuilder::echo_header).
+//!
+//! ```rust
+//! use axum::{Router, body::Body, routing::get};
+//! use http::Request;
+//! use tower::ServiceExt;
+//! use trace_id_layer::{TraceIdConfig, add_trace_id_middleware_with_config, testing};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+//! let router = add_trace_id_middleware_with_config(Router::new().route("/", get(|| async {})), config);
+//!
+//! let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+//! let uuid = testing::assert_trace_id_header(&response);
+//! assert_eq!(uuid.get_version_num(), 7);
+//! # }
+//! ```
+
+use http::Response;
+use uuid::Uuid;
+
+use crate::{TraceId, TraceIdConfig};
+
+/// Asserts that `response` carries a valid UUID trace ID in the
+/// [`TRACE_ID_HEADER`](crate::TRACE_ID_HEADER), and returns it.
+///
+/// # Panics
+///
+/// If the header is missing, or isn't a UUID.
+#[track_caller]
+pub fn assert_trace_id_header<B>(response: &Response<B>) -> Uuid {
+    assert_trace_id_header_with_config(response, &TraceIdConfig::default())
+}
+
+/// Same as [`assert_trace_id_header`], but reads the header configured in `config`.
+///
+/// # Panics
+///
+/// If the header is missing, or isn't a UUID.
+#[track_caller]
+pub fn assert_trace_id_header_with_config<B>(
+    response: &Response<B>,
+    config: &TraceIdConfig,
+) -> Uuid {
+    let header_name = config.header_name();
+    let Some(value) = response.headers().get(header_name) else {
+        panic!("response has no '{header_name}' header. Is echo_header enabled?");
+    };
+    match TraceId::try_from(value).map(|trace_id| trace_id.uuid()) {
+        Ok(Some(uuid)) => uuid,
+        Ok(None) => unreachable!("headers are parsed as UUIDs"),
+        Err(e) => panic!("response has an invalid '{header_name}' header: {e}"),
+    }
+}
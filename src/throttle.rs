@@ -0,0 +1,52 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Caps how many times a log line is emitted per minute, counting the suppressed ones.
+#[derive(Debug, Clone)]
+pub(crate) struct LogThrottle {
+    per_minute: u32,
+    window: Arc<Mutex<Window>>,
+}
+
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    logged: u32,
+    suppressed: u64,
+}
+
+impl LogThrottle {
+    pub(crate) fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            window: Arc::new(Mutex::new(Window {
+                start: Instant::now(),
+                logged: 0,
+                suppressed: 0,
+            })),
+        }
+    }
+
+    /// Whether to log now. If so, also returns how many lines were suppressed in the previous
+    /// window.
+    pub(crate) fn admit(&self) -> Option<u64> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let mut suppressed = 0;
+        if window.start.elapsed() >= WINDOW {
+            suppressed = std::mem::take(&mut window.suppressed);
+            window.start = Instant::now();
+            window.logged = 0;
+        }
+        if window.logged < self.per_minute {
+            window.logged += 1;
+            Some(suppressed)
+        } else {
+            window.suppressed += 1;
+            None
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::convert::Infallible;
+
+use axum::extract::OptionalFromRequestParts;
+use http::{HeaderMap, HeaderName, HeaderValue, request::Parts};
+
+use crate::log::debug;
+
+/// The W3C `traceparent` header.
+pub const TRACEPARENT_HEADER: HeaderName = HeaderName::from_static("traceparent");
+
+/// The W3C `tracestate` header.
+pub const TRACESTATE_HEADER: HeaderName = HeaderName::from_static("tracestate");
+
+/// The W3C [Trace Context](https://www.w3.org/TR/trace-context/) headers of a request, kept as
+/// they were sent, so they're forwarded unchanged to the services it calls.
+///
+/// The trace ID is still read from the configured header: `traceparent` isn't parsed beyond
+/// checking its shape (`version-trace_id-parent_id-flags`, in lowercase hex), and is dropped,
+/// along with `tracestate`, if it's malformed. `tracestate` is validated loosely, so vendor
+/// entries this crate doesn't understand are kept as is. Multiple `tracestate` headers are
+/// combined into one, as the spec allows. A `tracestate` without a `traceparent` is ignored, as
+/// the spec requires.
+///
+/// Enable it with [`trace_context`](crate::TraceIdConfigBuilder::trace_context), then use it as an
+/// optional extractor, and [`inject`](Self::inject) it into outbound requests, or use
+/// [`RequestContext::inject`](crate::RequestContext::inject), which does.
+///
+/// ```rust
+/// use http::HeaderMap;
+/// use trace_id_layer::TraceContext;
+///
+/// async fn my_handler(trace_context: Option<TraceContext>) -> HeaderMap {
+///     let mut outbound = HeaderMap::new();
+///     if let Some(trace_context) = trace_context {
+///         trace_context.inject(&mut outbound);
+///     }
+///     outbound
+/// }
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap());
+/// headers.append("tracestate", "congo=t61rcWkgMzE".parse().unwrap());
+/// headers.append("tracestate", "rojo=00f067aa0ba902b7".parse().unwrap());
+/// let trace_context = TraceContext::from_headers(&headers).unwrap();
+/// assert_eq!(trace_context.tracestate().unwrap(), "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7");
+///
+/// let mut outbound = HeaderMap::new();
+/// trace_context.inject(&mut outbound);
+/// assert_eq!(outbound["traceparent"], "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+/// assert_eq!(outbound["tracestate"], "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    traceparent: HeaderValue,
+    tracestate: Option<HeaderValue>,
+}
+
+impl TraceContext {
+    /// Reads the `traceparent` and `tracestate` headers in `headers`, if `traceparent` is present
+    /// and well-formed.
+    ///
+    /// ```rust
+    /// use http::HeaderMap;
+    /// use trace_id_layer::TraceContext;
+    ///
+    /// let from = |traceparent: &str| {
+    ///     let mut headers = HeaderMap::new();
+    ///     headers.insert("traceparent", traceparent.parse().unwrap());
+    ///     TraceContext::from_headers(&headers)
+    /// };
+    /// assert!(from("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_some());
+    /// // A future version may append fields, version 00 may not
+    /// assert!(from("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00").is_some());
+    /// assert!(from("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00").is_none());
+    /// // All-zero IDs are invalid
+    /// assert!(from("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+    /// assert!(from("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01").is_none());
+    /// ```
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let traceparent = headers.get(TRACEPARENT_HEADER)?;
+        if !is_traceparent(traceparent.as_bytes()) {
+            debug!("Ignoring malformed traceparent and its tracestate");
+            return None;
+        }
+        let tracestate = headers
+            .get_all(TRACESTATE_HEADER)
+            .iter()
+            .map(HeaderValue::as_bytes)
+            .filter(|value| !value.trim_ascii().is_empty())
+            .collect::<Vec<_>>();
+        let tracestate = (!tracestate.is_empty())
+            .then(|| HeaderValue::from_bytes(&tracestate.join(&b',')).ok())
+            .flatten();
+        Some(Self {
+            traceparent: traceparent.clone(),
+            tracestate,
+        })
+    }
+
+    /// The `traceparent` header.
+    pub fn traceparent(&self) -> &HeaderValue {
+        &self.traceparent
+    }
+
+    /// The `tracestate` header, if one was sent.
+    pub fn tracestate(&self) -> Option<&HeaderValue> {
+        self.tracestate.as_ref()
+    }
+
+    /// Sets the `traceparent` header, and the `tracestate` header if one was sent, in `headers`
+    /// for an outbound request, unchanged.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        headers.insert(TRACEPARENT_HEADER, self.traceparent.clone());
+        match &self.tracestate {
+            Some(tracestate) => {
+                headers.insert(TRACESTATE_HEADER, tracestate.clone());
+            }
+            None => {
+                headers.remove(TRACESTATE_HEADER);
+            }
+        }
+    }
+}
+
+/// Whether `value` is shaped like a `traceparent`: a version, a 32 digit trace ID, a 16 digit
+/// parent ID and flags, in lowercase hex, with any fields a future version appends. The IDs can't
+/// be all zeros.
+fn is_traceparent(value: &[u8]) -> bool {
+    let fields = value.split(|&byte| byte == b'-').collect::<Vec<_>>();
+    let [version, trace_id, parent_id, flags, rest @ ..] = fields.as_slice() else {
+        return false;
+    };
+    let is_hex = |field: &[u8], len| {
+        field.len() == len
+            && field
+                .iter()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let is_zero = |field: &[u8]| field.iter().all(|&byte| byte == b'0');
+    is_hex(version, 2)
+        && is_hex(trace_id, 32)
+        && is_hex(parent_id, 16)
+        && is_hex(flags, 2)
+        && !is_zero(trace_id)
+        && !is_zero(parent_id)
+        // Version ff is invalid, and version 00 has exactly four fields
+        && match *version {
+            b"ff" => false,
+            b"00" => rest.is_empty(),
+            _ => true,
+        }
+}
+
+impl<S> OptionalFromRequestParts<S> for TraceContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<TraceContext>().cloned())
+    }
+}
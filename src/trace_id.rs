@@ -0,0 +1,743 @@
+use std::{
+    convert::Infallible,
+    fmt::Display,
+    ops::Deref,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    response::{IntoResponse, Response},
+};
+use http::{HeaderMap, HeaderValue, StatusCode, header::ToStrError, request::Parts};
+use uuid::{
+    Uuid,
+    fmt::{Hyphenated, Simple},
+};
+
+use crate::{IdFormat, InboundTrust, TRACE_ID_HEADER, TraceIdConfig, core, log::error};
+
+/// The trace ID extracted or generated for this request.
+///
+/// Use this as an extractor in your handlers to access the trace ID:
+///
+/// ```rust
+/// use trace_id_layer::TraceId;
+///
+/// async fn my_handler(trace_id: TraceId) -> String {
+///     format!("Request trace ID: {}", trace_id)
+/// }
+/// ```
+///
+/// `TraceId` only reads the request parts, so it combines with extractors consuming the body,
+/// such as `Json`, as long as those come last, like any axum extractor. Body-consuming extractors
+/// of your own, and handlers taking the whole `Request`, find it in the request extensions:
+///
+/// ```rust
+/// use axum::{Json, extract::{FromRequest, Request}, response::IntoResponse};
+/// use serde::Deserialize;
+/// use trace_id_layer::TraceId;
+///
+/// #[derive(Deserialize)]
+/// struct CreateUser {
+///     name: String,
+/// }
+///
+/// async fn create_user(trace_id: TraceId, Json(user): Json<CreateUser>) -> String {
+///     format!("{trace_id}: created {}", user.name)
+/// }
+///
+/// async fn raw(request: Request) -> String {
+///     let trace_id = request.extensions().get::<TraceId>().cloned();
+///     let body = axum::body::to_bytes(request.into_body(), usize::MAX).await.unwrap();
+///     format!("{trace_id:?}: {} bytes", body.len())
+/// }
+///
+/// struct Audited(String, TraceId);
+///
+/// impl<S: Send + Sync> FromRequest<S> for Audited {
+///     type Rejection = axum::response::Response;
+///
+///     async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+///         let trace_id = request.extensions().get::<TraceId>().cloned().unwrap();
+///         let body = String::from_request(request, state).await.map_err(IntoResponse::into_response)?;
+///         Ok(Audited(body, trace_id))
+///     }
+/// }
+///
+/// # let _: axum::Router = axum::Router::new()
+/// #     .route("/users", axum::routing::post(create_user))
+/// #     .route("/raw", axum::routing::post(raw))
+/// #     .route("/audited", axum::routing::post(|Audited(body, trace_id): Audited| async move { format!("{trace_id}: {body}") }));
+/// ```
+///
+/// A trace ID is usually a [`Uuid`], but it can also hold the numeric and free-form string IDs
+/// used by other propagation formats. The UUID case is stored inline, so it never allocates.
+///
+/// ```rust
+/// use trace_id_layer::TraceId;
+/// use uuid::Uuid;
+///
+/// let uuid = Uuid::now_v7();
+/// assert_eq!(TraceId::from(uuid).uuid(), Some(uuid));
+///
+/// let numeric = TraceId::from(4_611_686_018_427_387_904_u64);
+/// assert_eq!(numeric.uuid(), None);
+/// assert_eq!(numeric.to_string(), "4611686018427387904");
+///
+/// let text = TraceId::from_text("req_01HV6B");
+/// assert_eq!(text.as_text(), Some("req_01HV6B"));
+/// ```
+///
+/// Trace IDs read from headers are normalized on ingestion, whatever casing they were sent in:
+/// UUIDs to hyphenated lowercase, so exact-match log searches don't miss an ID a client sent in
+/// uppercase, and [short IDs](crate::TraceIdConfigBuilder::short_ids) to uppercase, as Crockford
+/// base32 is written. The extractor, the span, the logs and the echoed header all get the
+/// normalized form. Only the [`RawTraceHeader`] extractor and
+/// [`echo_verbatim`](crate::TraceIdConfigBuilder::echo_verbatim) keep the value as sent. IDs
+/// returned by a [`source`](crate::TraceIdConfigBuilder::source) callback are used as is.
+///
+/// `TraceId` is not `Copy`, since the free-form case holds a shared string. Cloning is still
+/// cheap: it never allocates, for UUIDs and numeric IDs it's a plain copy, and for strings a
+/// reference count increment. Where a `Copy` handle helps, e.g. to move the ID into several
+/// closures, take the [`Uuid`] out with [`uuid`](Self::uuid):
+///
+/// ```rust
+/// use trace_id_layer::TraceId;
+///
+/// async fn my_handler(trace_id: TraceId) {
+///     let uuid = trace_id.uuid().expect("UUID trace ID");
+///     let log = move |step: &str| println!("{uuid}: {step}");
+///     let retry = move || println!("{uuid}: retrying");
+///     log("start");
+///     retry();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TraceId(Repr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Uuid(Uuid),
+    Numeric(u64),
+    Text(Arc<str>),
+}
+
+/// Renders the trace ID in the canonical form of its representation: hyphenated lowercase for
+/// UUIDs, decimal for numeric IDs (as Datadog sends them), and verbatim for free-form IDs, e.g.
+/// ULIDs in Crockford base32.
+///
+/// This is also what the middleware writes to headers, with the default
+/// [`IdFormat`](crate::IdFormat), and what's recorded on the span.
+///
+/// `Display` stays the same whatever the configured [`IdFormat`](crate::IdFormat), so
+/// `format!("{trace_id}")` is stable across deployments. To interpolate the ID as it appears in
+/// headers, extract a [`FormattedTraceId`](crate::FormattedTraceId) instead, or use
+/// [`display`](TraceId::display) with a given format.
+///
+/// ```rust
+/// use trace_id_layer::{TraceId, TraceIdConfig};
+/// use uuid::Uuid;
+///
+/// let uuid = TraceId::from(Uuid::parse_str("0192F0C45B8E7C3A9D2E6F1A2B3C4D5E").unwrap());
+/// assert_eq!(uuid.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+///
+/// let numeric = TraceId::from(u64::MAX);
+/// assert_eq!(numeric.to_string(), "18446744073709551615");
+///
+/// let ulid = TraceId::from_text("01HV6B8Z2K3M4N5P6Q7R8S9T0V");
+/// assert_eq!(ulid.to_string(), "01HV6B8Z2K3M4N5P6Q7R8S9T0V");
+///
+/// let config = TraceIdConfig::default();
+/// for trace_id in [uuid, numeric, ulid] {
+///     assert_eq!(config.render(&trace_id), trace_id.to_string());
+/// }
+/// ```
+impl Display for TraceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Repr::Uuid(uuid) => write!(f, "{uuid}"),
+            Repr::Numeric(id) => write!(f, "{id}"),
+            Repr::Text(id) => f.write_str(id),
+        }
+    }
+}
+
+/// A [`TraceId`] displayed in an [`IdFormat`], returned by [`TraceId::display`].
+struct FormatDisplay<'a>(&'a TraceId, IdFormat);
+
+impl Display for FormatDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.0.0, self.1) {
+            (Repr::Uuid(uuid), IdFormat::Simple) => write!(f, "{}", uuid.simple()),
+            _ => self.0.fmt(f),
+        }
+    }
+}
+
+impl From<Uuid> for TraceId {
+    fn from(uuid: Uuid) -> Self {
+        Self(Repr::Uuid(uuid))
+    }
+}
+
+impl From<u64> for TraceId {
+    fn from(id: u64) -> Self {
+        Self(Repr::Numeric(id))
+    }
+}
+
+impl TraceId {
+    /// Wraps an arbitrary string ID (hex, prefixed, ...) that isn't a UUID or a number.
+    pub fn from_text(id: impl Into<Arc<str>>) -> Self {
+        Self(Repr::Text(id.into()))
+    }
+
+    /// The inner [`Uuid`], if this trace ID is one.
+    pub fn uuid(&self) -> Option<Uuid> {
+        match self.0 {
+            Repr::Uuid(uuid) => Some(uuid),
+            _ => None,
+        }
+    }
+
+    /// Creates a UUID trace ID from its 128-bit integer value, as stored by databases without a
+    /// native UUID type.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id = TraceId::from_u128(0x0192f0c4_5b8e_7c3a_9d2e_6f1a2b3c4d5e);
+    /// assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// assert_eq!(trace_id.as_u128(), Some(0x0192f0c4_5b8e_7c3a_9d2e_6f1a2b3c4d5e));
+    /// ```
+    pub const fn from_u128(id: u128) -> Self {
+        Self(Repr::Uuid(Uuid::from_u128(id)))
+    }
+
+    /// Creates a UUID trace ID from its 16 big-endian bytes, as sent by binary protocols.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let bytes = *b"\x01\x92\xf0\xc4\x5b\x8e\x7c\x3a\x9d\x2e\x6f\x1a\x2b\x3c\x4d\x5e";
+    /// let trace_id = TraceId::from_bytes(bytes);
+    /// assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// assert_eq!(trace_id.to_bytes(), Some(bytes));
+    /// ```
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(Repr::Uuid(Uuid::from_bytes(bytes)))
+    }
+
+    /// The 128-bit integer value of this trace ID, if it's a UUID.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// let id = trace_id.as_u128().unwrap();
+    /// assert_eq!(TraceId::from_u128(id), trace_id);
+    ///
+    /// assert_eq!(TraceId::from(42_u64).as_u128(), None);
+    /// ```
+    pub fn as_u128(&self) -> Option<u128> {
+        self.uuid().map(|uuid| uuid.as_u128())
+    }
+
+    /// The 16 big-endian bytes of this trace ID, if it's a UUID.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// let bytes = trace_id.to_bytes().unwrap();
+    /// assert_eq!(TraceId::from_bytes(bytes), trace_id);
+    ///
+    /// assert_eq!(TraceId::from_text("req_01HV6B").to_bytes(), None);
+    /// ```
+    pub fn to_bytes(&self) -> Option<[u8; 16]> {
+        self.uuid().map(|uuid| uuid.into_bytes())
+    }
+
+    /// When this trace ID was generated, read from a UUIDv7's embedded timestamp, with millisecond
+    /// precision.
+    ///
+    /// Only valid for UUIDv7 trace IDs, as generated by default: `None` for other UUID versions
+    /// and representations. A client-supplied ID's timestamp is whatever the client put there.
+    ///
+    /// ```rust
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// assert_eq!(trace_id.timestamp(), Some(UNIX_EPOCH + Duration::from_millis(1_730_616_253_326)));
+    ///
+    /// let trace_id: TraceId = "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap();
+    /// assert_eq!(trace_id.timestamp(), None);
+    /// ```
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        let uuid = self.uuid().filter(|uuid| uuid.get_version_num() == 7)?;
+        let (secs, nanos) = uuid.get_timestamp()?.to_unix();
+        Some(UNIX_EPOCH + Duration::new(secs, nanos))
+    }
+
+    /// The node ID embedded by [`node_id`](crate::TraceIdConfigBuilder::node_id): the low 16 bits
+    /// of a UUIDv7.
+    ///
+    /// Any UUIDv7 has these bits, random unless they were set by an instance configured with a
+    /// node ID, so only rely on this for trace IDs generated by such a fleet.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c002a".parse().unwrap();
+    /// assert_eq!(trace_id.node_id(), Some(42));
+    /// ```
+    pub fn node_id(&self) -> Option<u16> {
+        self.uuid()
+            .filter(|uuid| uuid.get_version_num() == 7)
+            .map(|uuid| uuid.as_u128() as u16)
+    }
+
+    /// Displays this trace ID with UUIDs in `format`, rather than always hyphenated like
+    /// [`Display`](#impl-Display-for-TraceId). Other representations are displayed unchanged.
+    ///
+    /// ```rust
+    /// use trace_id_layer::{IdFormat, TraceId};
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// assert_eq!(trace_id.display(IdFormat::Simple).to_string(), "0192f0c45b8e7c3a9d2e6f1a2b3c4d5e");
+    /// ```
+    pub fn display(&self, format: IdFormat) -> impl Display + '_ {
+        FormatDisplay(self, format)
+    }
+
+    /// Reads the trace ID from `headers` the way the middleware configured by `config` does, e.g.
+    /// in a message consumer reconstructing the context from message headers.
+    ///
+    /// Each configured source is tried in order, and the first match returned:
+    ///
+    /// 1. the [trace ID header](crate::TraceIdConfigBuilder::header_name), in any accepted UUID
+    ///    form, or as a [short ID](crate::TraceIdConfigBuilder::short_ids) when enabled,
+    /// 2. the [fallback headers](crate::TraceIdConfigBuilder::fallback_headers), in order.
+    ///
+    /// Unlike in the middleware, an invalid trace ID header is skipped rather than handled as
+    /// configured with [`invalid_header`](crate::TraceIdConfigBuilder::invalid_header), and
+    /// signatures aren't checked. With an [untrusted](crate::InboundTrust::Untrusted) inbound
+    /// header, nothing is read. Returns `None` when no source matches: generate a trace ID then.
+    ///
+    /// ```rust
+    /// use http::HeaderMap;
+    /// use trace_id_layer::{TraceId, TraceIdConfig};
+    ///
+    /// let config = TraceIdConfig::builder()
+    ///     .fallback_headers(["x-request-id", "x-correlation-id"])
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(TraceId::from_headers(&headers, &config), None);
+    ///
+    /// headers.insert("x-correlation-id", "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap());
+    /// headers.insert("x-request-id", "not-a-uuid".parse().unwrap());
+    /// let trace_id = TraceId::from_headers(&headers, &config).unwrap();
+    /// assert_eq!(trace_id.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    ///
+    /// headers.insert("x-request-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c002a".parse().unwrap());
+    /// let trace_id = TraceId::from_headers(&headers, &config).unwrap();
+    /// assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c002a");
+    ///
+    /// headers.insert("x-trace-id", "invalid".parse().unwrap());
+    /// let trace_id = TraceId::from_headers(&headers, &config).unwrap();
+    /// assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c002a");
+    ///
+    /// headers.insert("x-trace-id", "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap());
+    /// let trace_id = TraceId::from_headers(&headers, &config).unwrap();
+    /// assert_eq!(trace_id.to_string(), "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// ```
+    pub fn from_headers(headers: &HeaderMap, config: &TraceIdConfig) -> Option<Self> {
+        if config.inbound_trust == InboundTrust::Untrusted {
+            return None;
+        }
+        let trace_id = config.extract(headers).ok().flatten();
+        trace_id.or_else(|| {
+            config
+                .extract_fallback(headers)
+                .map(|(trace_id, _)| trace_id)
+        })
+    }
+
+    /// The inner numeric ID, if this trace ID is one.
+    pub fn as_numeric(&self) -> Option<u64> {
+        match self.0 {
+            Repr::Numeric(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The inner string ID, if this trace ID is neither a UUID nor a number.
+    pub fn as_text(&self) -> Option<&str> {
+        match &self.0 {
+            Repr::Text(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Fills `template` with this trace ID, e.g. to link to a log search.
+    ///
+    /// Every `{id}` in `template` is replaced by the percent-encoded trace ID.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// assert_eq!(
+    ///     trace_id.log_url("https://logs.example.com/search?q={id}"),
+    ///     "https://logs.example.com/search?q=0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e",
+    /// );
+    ///
+    /// let trace_id = TraceId::from_text("req 1/2");
+    /// assert_eq!(trace_id.log_url("/search?q={id}"), "/search?q=req%201%2F2");
+    /// ```
+    pub fn log_url(&self, template: &str) -> String {
+        template.replace("{id}", &percent_encode(&self.to_string()))
+    }
+
+    /// This trace ID as a header value, in its [`Display`](#impl-Display-for-TraceId) form, e.g.
+    /// to set it on a hand-built downstream request.
+    ///
+    /// Infallible: UUIDs and numeric IDs are always valid header values. So are free-form IDs
+    /// read from a header, while those created with [`from_text`](Self::from_text) that aren't
+    /// are percent-encoded.
+    ///
+    /// ```rust
+    /// use trace_id_layer::TraceId;
+    ///
+    /// let trace_id: TraceId = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e".parse().unwrap();
+    /// let value = trace_id.to_header_value();
+    /// assert_eq!(value, "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+    /// assert_eq!(TraceId::try_from(&value).unwrap(), trace_id);
+    ///
+    /// assert_eq!(TraceId::from_text("req\n1").to_header_value(), "req%0A1");
+    /// ```
+    pub fn to_header_value(&self) -> HeaderValue {
+        let id = self.to_string();
+        HeaderValue::try_from(&id).unwrap_or_else(|_| {
+            HeaderValue::try_from(percent_encode(&id))
+                .expect("percent-encoded IDs are valid header values")
+        })
+    }
+}
+
+/// Percent-encodes every byte of `id` but unreserved URL characters.
+fn percent_encode(id: &str) -> String {
+    id.bytes()
+        .fold(String::with_capacity(id.len()), |mut encoded, byte| {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(byte as char)
+                }
+                _ => encoded.push_str(&format!("%{byte:02X}")),
+            }
+            encoded
+        })
+}
+
+/// The inbound trace ID of a request handled in chain mode.
+///
+/// With [`chain`](crate::TraceIdConfigBuilder::chain) enabled, each service generates its own
+/// [`TraceId`] for the hop and keeps the inbound one as its parent. The parent is recorded as the
+/// `parent_id` span field and is available as an extractor. Requests that arrived without a trace
+/// ID have no parent, so extract it as an `Option`:
+///
+/// ```rust
+/// use trace_id_layer::{ParentId, TraceId};
+///
+/// async fn my_handler(trace_id: TraceId, parent_id: Option<ParentId>) -> String {
+///     match parent_id {
+///         Some(parent_id) => format!("{trace_id} (called by {parent_id})"),
+///         None => trace_id.to_string(),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParentId(pub(crate) TraceId);
+
+impl Deref for ParentId {
+    type Target = TraceId;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for ParentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for ParentId
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<ParentId>().cloned())
+    }
+}
+
+/// The trace ID header exactly as the client sent it, before any parsing or normalization.
+///
+/// Use this as an extractor when the verbatim value matters (echoing it back, validating a
+/// signature over it), since [`TraceId`] is canonicalized, e.g. a hyphenless UUID is rendered
+/// hyphenated. It's kept even when the value failed to parse and a new ID was generated.
+///
+/// Holds `None` when no trace ID header was sent.
+///
+/// ```rust
+/// use trace_id_layer::RawTraceHeader;
+///
+/// async fn my_handler(raw: RawTraceHeader) -> String {
+///     raw.as_str().unwrap_or("<none>").to_owned()
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTraceHeader(pub(crate) Option<HeaderValue>);
+
+impl RawTraceHeader {
+    /// The header value, if one was sent.
+    pub fn value(&self) -> Option<&HeaderValue> {
+        self.0.as_ref()
+    }
+
+    /// The header value as a string, if one was sent and it's visible ASCII.
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_ref().and_then(|value| value.to_str().ok())
+    }
+}
+
+impl<S> FromRequestParts<S> for RawTraceHeader
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        const ERR_MSG: &str =
+            "RawTraceHeader extension missing. Did you apply add_trace_id_middleware?";
+        parts
+            .extensions
+            .get::<RawTraceHeader>()
+            .cloned()
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, ERR_MSG))
+            .inspect_err(|e| error!("{ERR_MSG}. Error: '{e:?}'"))
+    }
+}
+
+/// The error returned when a header value or string isn't a valid trace ID.
+#[derive(Debug)]
+pub enum ParseTraceIdError {
+    /// The header value contains non-visible-ASCII bytes.
+    NotAscii(ToStrError),
+    /// The value isn't a valid UUID.
+    InvalidUuid { value: String, error: uuid::Error },
+}
+
+impl Display for ParseTraceIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseTraceIdError::NotAscii(error) => {
+                write!(f, "Unable to convert trace-id header to string: '{error}'")
+            }
+            ParseTraceIdError::InvalidUuid { value, error } => {
+                write!(f, "Unable to parse trace-id '{value}' as a Uuid: '{error}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseTraceIdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseTraceIdError::NotAscii(error) => Some(error),
+            ParseTraceIdError::InvalidUuid { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Parses a UUID trace ID, as sent in the `x-trace-id` header.
+///
+/// Accepted forms, in either case, all normalized to the canonical hyphenated lowercase output:
+///
+/// - hyphenated: `0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e`
+/// - simple: `0192f0c45b8e7c3a9d2e6f1a2b3c4d5e`
+/// - braced: `{0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}`
+/// - URN: `urn:uuid:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e`
+///
+/// ```rust
+/// use trace_id_layer::TraceId;
+///
+/// let canonical = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e";
+/// for input in [
+///     "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e",
+///     "0192F0C4-5B8E-7C3A-9D2E-6F1A2B3C4D5E",
+///     "0192f0c45b8e7c3a9d2e6f1a2b3c4d5e",
+///     "{0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}",
+///     "urn:uuid:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e",
+///     "URN:UUID:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e",
+/// ] {
+///     assert_eq!(TraceId::try_from(input).unwrap().to_string(), canonical);
+/// }
+///
+/// assert!(TraceId::try_from("{0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e").is_err());
+/// assert!(TraceId::try_from("urn:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e").is_err());
+/// // Only one layer of wrapping
+/// for input in [
+///     "{{0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}}",
+///     "urn:uuid:{0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}",
+///     "{urn:uuid:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e}",
+///     "URN:UUID:urn:uuid:0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e",
+///     "{0192f0c45b8e7c3a9d2e6f1a2b3c4d5e}",
+/// ] {
+///     assert!(TraceId::try_from(input).is_err(), "{input}");
+/// }
+///
+/// let error = TraceId::try_from("not-a-uuid").unwrap_err();
+/// assert!(error.to_string().contains("not-a-uuid"));
+/// ```
+impl TryFrom<&str> for TraceId {
+    type Error = ParseTraceIdError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        const URN_PREFIX: &str = "urn:uuid:";
+        // Unwrapped here, then only the bare forms are parsed: `Uuid::try_parse` would also accept
+        // a braced or URN form, letting a second layer of wrapping through
+        let uuid = match value.get(..URN_PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(URN_PREFIX) => &value[URN_PREFIX.len()..],
+            _ => value
+                .strip_prefix('{')
+                .and_then(|braced| braced.strip_suffix('}'))
+                .unwrap_or(value),
+        };
+        // Only the hyphenated form can be wrapped
+        let uuid = match uuid.len() {
+            32 if uuid.len() == value.len() => uuid.parse::<Simple>().map(Simple::into_uuid),
+            _ => uuid.parse::<Hyphenated>().map(Hyphenated::into_uuid),
+        };
+        uuid.map(TraceId::from)
+            .map_err(|error| ParseTraceIdError::InvalidUuid {
+                value: value.to_owned(),
+                error,
+            })
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = ParseTraceIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TraceId::try_from(s)
+    }
+}
+
+/// Parses a UUID trace ID from a header value.
+///
+/// ```rust
+/// use http::HeaderValue;
+/// use trace_id_layer::{ParseTraceIdError, TraceId};
+///
+/// let value = HeaderValue::from_static("0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e");
+/// assert!(TraceId::try_from(&value).is_ok());
+///
+/// let value = HeaderValue::from_bytes(b"\xfa\xfb").unwrap();
+/// assert!(matches!(TraceId::try_from(&value), Err(ParseTraceIdError::NotAscii(_))));
+/// ```
+impl TryFrom<&HeaderValue> for TraceId {
+    type Error = ParseTraceIdError;
+
+    fn try_from(value: &HeaderValue) -> Result<Self, Self::Error> {
+        value
+            .to_str()
+            .map_err(ParseTraceIdError::NotAscii)
+            .and_then(TraceId::try_from)
+    }
+}
+
+impl<S> FromRequestParts<S> for TraceId
+where
+    S: Send + Sync,
+{
+    type Rejection = MissingTraceId;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<TraceId>().cloned().ok_or_else(|| {
+            let rejection = MissingTraceId {
+                trace_id: core::generate(),
+            };
+            error!(
+                trace_id = %rejection.trace_id,
+                "{}. Assigned a one-off trace_id",
+                MissingTraceId::MESSAGE
+            );
+            rejection
+        })
+    }
+}
+
+/// The rejection returned by the [`TraceId`] extractor when the middleware wasn't applied.
+///
+/// Responds with `500 Internal Server Error`, but still carries a trace ID header: a one-off ID
+/// is generated and logged, so the failed request remains searchable.
+///
+/// ```rust
+/// use axum::{Router, body::Body, routing::get};
+/// use http::{Request, StatusCode};
+/// use tower::ServiceExt;
+/// use trace_id_layer::TraceId;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // Middleware not applied
+/// let router = Router::new().route("/", get(|_: TraceId| async {}));
+///
+/// let response = router.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap();
+/// assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+/// assert!(response.headers().contains_key("x-trace-id"));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MissingTraceId {
+    trace_id: TraceId,
+}
+
+impl MissingTraceId {
+    const MESSAGE: &str = "TraceId extension missing. Did you apply add_trace_id_middleware?";
+
+    /// The one-off trace ID sent with the rejection.
+    pub fn trace_id(&self) -> &TraceId {
+        &self.trace_id
+    }
+}
+
+impl Display for MissingTraceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(Self::MESSAGE)
+    }
+}
+
+impl IntoResponse for MissingTraceId {
+    fn into_response(self) -> Response {
+        let mut response = (StatusCode::INTERNAL_SERVER_ERROR, Self::MESSAGE).into_response();
+        let value = self.trace_id.to_header_value();
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+        response
+    }
+}
@@ -0,0 +1,276 @@
+//! The log lines the middleware and its helpers produce, as a `fmt` subscriber writes them.
+#![cfg(feature = "tracing")]
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{MatchedPath, Request},
+    middleware::{self, Next},
+    response::Response,
+    routing::get,
+};
+use tower::ServiceExt;
+use trace_id_layer::{
+    TraceIdConfig, add_trace_id_middleware, add_trace_id_middleware_with_config, child_span,
+    full_observability, record_middleware_latency, spawn_traced, trace_id_span_fields,
+};
+use tracing::{Level, subscriber::DefaultGuard};
+
+const TRACE_ID: &str = "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e";
+
+/// The output of the subscriber set by [`capture`].
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Logs {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// The first line containing `needle`.
+    fn line(&self, needle: &str) -> String {
+        let logs = self.contents();
+        match logs.lines().find(|line| line.contains(needle)) {
+            Some(line) => line.to_owned(),
+            None => panic!("No line containing {needle:?} in:\n{logs}"),
+        }
+    }
+}
+
+/// Captures the events up to `level` on this thread until the guard is dropped.
+fn capture(level: Level) -> (Logs, DefaultGuard) {
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_max_level(level)
+        .with_writer(move || writer.clone())
+        .finish();
+    (logs, tracing::subscriber::set_default(subscriber))
+}
+
+fn request(uri: &str) -> Request {
+    Request::get(uri)
+        .header("x-trace-id", TRACE_ID)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn handler_logs_carry_the_trace_id() {
+    async fn load_user() {
+        tracing::info!("loading user");
+    }
+
+    async fn handler() {
+        tokio::task::yield_now().await;
+        load_user().await;
+    }
+
+    let (logs, _guard) = capture(Level::INFO);
+    let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+    router.oneshot(request("/")).await.unwrap();
+
+    let line = logs.line("loading user");
+    assert!(line.contains(&format!("trace_id={TRACE_ID}")), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn inner_middleware_logs_carry_the_trace_id() {
+    async fn auth(request: Request, next: Next) -> Response {
+        tracing::info!("checking credentials");
+        next.run(request).await
+    }
+
+    let (logs, _guard) = capture(Level::INFO);
+    let router = Router::new()
+        .route("/", get(|| async {}))
+        .layer(middleware::from_fn(auth));
+    let router = add_trace_id_middleware(router);
+    router.oneshot(request("/")).await.unwrap();
+
+    let line = logs.line("checking credentials");
+    assert!(line.contains(&format!("trace_id={TRACE_ID}")), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn child_span_carries_the_trace_id() {
+    async fn handler() {
+        let user_id = 42;
+        let _span = child_span!("load_user", user_id).entered();
+        tracing::info!("loading user");
+    }
+
+    let (logs, _guard) = capture(Level::INFO);
+    let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+    router.oneshot(request("/")).await.unwrap();
+
+    let line = logs.line("loading user");
+    let span = format!("load_user{{trace_id={TRACE_ID} user_id=42}}");
+    assert!(line.contains(&span), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn span_fields_are_recorded() {
+    async fn handler(path: MatchedPath) {
+        tracing::Span::current().record("route", path.as_str());
+        tracing::info!("loading user");
+    }
+
+    let (logs, _guard) = capture(Level::INFO);
+    let config = TraceIdConfig::builder()
+        .span_fields(trace_id_span_fields!(trace_id, method, route, status))
+        .build()
+        .unwrap();
+    let router = Router::new().route("/users/{id}", get(handler));
+    let router = add_trace_id_middleware_with_config(router, config);
+    router.oneshot(request("/users/42")).await.unwrap();
+
+    let line = logs.line("loading user");
+    let span = format!("http-request{{trace_id={TRACE_ID} method=GET route=\"/users/{{id}}\"}}");
+    assert!(line.contains(&span), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn spawn_traced_logs_in_a_child_span() {
+    async fn handler() {
+        spawn_traced(async { tracing::info!("sending email") })
+            .await
+            .unwrap();
+    }
+
+    let (logs, _guard) = capture(Level::INFO);
+    let router = add_trace_id_middleware(Router::new().route("/", get(handler)));
+    router.oneshot(request("/")).await.unwrap();
+
+    let line = logs.line("sending email");
+    assert!(
+        line.contains(&format!("trace_id={TRACE_ID}}}:spawned")),
+        "{line}"
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn middleware_latency_is_recorded() {
+    let (logs, _guard) = capture(Level::INFO);
+    let router = Router::new()
+        .route("/", get(|| async { tracing::info!("handling") }))
+        .route_layer(middleware::from_fn(record_middleware_latency));
+    let router = add_trace_id_middleware(router);
+    router.oneshot(request("/")).await.unwrap();
+
+    let line = logs.line("handling");
+    assert!(line.contains("middleware_latency="), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn full_observability_logs_the_request_id() {
+    let (logs, _guard) = capture(Level::INFO);
+    let router = full_observability().apply(Router::new().route("/", get(|| async {})));
+    let response = router.oneshot(request("/")).await.unwrap();
+
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    let line = logs.line("Request completed");
+    assert!(
+        line.contains(&format!("request_id=\"{request_id}\"")),
+        "{line}"
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn full_observability_replaces_an_inbound_request_id() {
+    let (logs, _guard) = capture(Level::INFO);
+    let router = full_observability().apply(Router::new().route("/", get(|| async {})));
+    let mut request = request("/");
+    request
+        .headers_mut()
+        .insert("x-request-id", "upstream".parse().unwrap());
+    let response = router.oneshot(request).await.unwrap();
+
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert_ne!(request_id, "upstream");
+    let line = logs.line("Request completed");
+    assert!(
+        line.contains(&format!("request_id=\"{request_id}\"")),
+        "{line}"
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn full_observability_skips_request_ids_read_as_trace_ids() {
+    let (logs, _guard) = capture(Level::INFO);
+    let config = TraceIdConfig::builder()
+        .header_name("x-request-id")
+        .build()
+        .unwrap();
+    let router = full_observability()
+        .config(config)
+        .apply(Router::new().route("/", get(|| async {})));
+    let request = Request::get("/")
+        .header("x-request-id", TRACE_ID)
+        .body(Body::empty())
+        .unwrap();
+    router.oneshot(request).await.unwrap();
+
+    let line = logs.line("Request completed");
+    assert!(line.contains(&format!("trace_id={TRACE_ID}")), "{line}");
+    assert!(!line.contains("request_id="), "{line}");
+    logs.line("not adding the request ID layers");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn span_covers_body_logs_stream_end_in_the_span() {
+    let (logs, _guard) = capture(Level::DEBUG);
+    let config = TraceIdConfig::builder()
+        .span_covers_body(true)
+        .build()
+        .unwrap();
+    let router = Router::new().route("/download", get(|| async { "x".repeat(1024) }));
+    let router = add_trace_id_middleware_with_config(router, config);
+    let response = router.oneshot(request("/download")).await.unwrap();
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    let line = logs.line("Stream closed");
+    assert!(
+        line.contains(&format!("http-request{{trace_id={TRACE_ID}")),
+        "{line}"
+    );
+    assert!(line.ends_with("bytes=1024"), "{line}");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn span_covers_body_with_access_log_logs_one_line() {
+    let (logs, _guard) = capture(Level::DEBUG);
+    let config = TraceIdConfig::builder()
+        .span_covers_body(true)
+        .access_log(Level::INFO)
+        .build()
+        .unwrap();
+    let router = Router::new().route("/download", get(|| async { "x".repeat(1024) }));
+    let router = add_trace_id_middleware_with_config(router, config);
+    let response = router.oneshot(request("/download")).await.unwrap();
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    let logs = logs.contents();
+    assert!(!logs.contains("Stream closed"), "{logs}");
+    assert_eq!(logs.matches("Request completed").count(), 1, "{logs}");
+}
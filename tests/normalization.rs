@@ -0,0 +1,34 @@
+//! How trace IDs read from headers are normalized, and where the value as sent is kept.
+
+use axum::{Router, body::Body, routing::get};
+use http::Request;
+use tower::ServiceExt;
+use trace_id_layer::{RawTraceHeader, TraceId, TraceIdConfig, add_trace_id_middleware_with_config};
+
+#[tokio::test(flavor = "current_thread")]
+async fn echo_is_normalized_and_raw_header_is_kept() {
+    async fn handler(trace_id: TraceId, raw: RawTraceHeader) -> String {
+        format!("{trace_id} {}", raw.as_str().unwrap())
+    }
+
+    let config = TraceIdConfig::builder().echo_header(true).build().unwrap();
+    let router = Router::new().route("/", get(handler));
+    let router = add_trace_id_middleware_with_config(router, config);
+    let request = Request::get("/")
+        .header("x-trace-id", "0192F0C45B8E7C3A9D2E6F1A2B3C4D5E")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+
+    assert_eq!(
+        response.headers()["x-trace-id"],
+        "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e"
+    );
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        body,
+        "0192f0c4-5b8e-7c3a-9d2e-6f1a2b3c4d5e 0192F0C45B8E7C3A9D2E6F1A2B3C4D5E"
+    );
+}